@@ -0,0 +1,77 @@
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::path::{Path, PathBuf};
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+use crate::source::{FileMetadata, FileSource};
+use crate::Error;
+
+/// A [`FileSource`] over the entries of a zip archive, available with the `archive` feature.
+///
+/// Entry names are used as relative paths. Directory entries are skipped.
+///
+/// Only the central directory is read when the archive is opened. Comparing an entry however
+/// means decompressing it completely, so comparing two archives costs roughly as much as
+/// extracting both, just without writing anything to disk. Stored (uncompressed) entries are
+/// the cheapest to compare. Entries of different uncompressed sizes are told apart from the
+/// central directory alone. Modification times aren't reported, as zip stores them with a
+/// resolution of two seconds and without a time zone, so entries of the same size are always
+/// decompressed and hashed.
+///
+/// # Example
+///
+///```no_run
+/// use std::path::Path;
+/// use folder_compare::{FolderCompare, ZipSource};
+///
+/// let mut first = ZipSource::open(Path::new("/tmp/release-1.zip")).unwrap();
+/// let mut second = ZipSource::open(Path::new("/tmp/release-2.zip")).unwrap();
+///
+/// let result = FolderCompare::from_sources(&mut first, &mut second, &vec![]).unwrap();
+///```
+///
+pub struct ZipSource<R> {
+    archive: ZipArchive<R>,
+}
+
+impl ZipSource<File> {
+    /// Opens the zip archive at `path`.
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        ZipSource::new(File::open(path)?)
+    }
+}
+
+impl<R: Read + Seek> ZipSource<R> {
+    /// Reads a zip archive from any seekable reader.
+    pub fn new(reader: R) -> Result<Self, Error> {
+        Ok(ZipSource { archive: ZipArchive::new(reader)? })
+    }
+}
+
+impl<R: Read + Seek> FileSource for ZipSource<R> {
+    fn files(&mut self) -> Result<Vec<PathBuf>, Error> {
+        Ok(self.archive.file_names()
+            .filter(|name| !name.ends_with('/'))
+            .map(PathBuf::from)
+            .collect())
+    }
+
+    fn metadata(&mut self, path: &Path) -> Result<Option<FileMetadata>, Error> {
+        let name = path.to_string_lossy().replace('\\', "/");
+        match self.archive.by_name(&name) {
+            Ok(file) => Ok(Some(FileMetadata { size: file.size(), modified: None })),
+            Err(ZipError::FileNotFound) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn open(&mut self, path: &Path) -> Result<Option<Box<dyn Read + '_>>, Error> {
+        let name = path.to_string_lossy().replace('\\', "/");
+        match self.archive.by_name(&name) {
+            Ok(file) => Ok(Some(Box::new(file))),
+            Err(ZipError::FileNotFound) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use fxhash::{FxHashSet, FxHasher};
+use regex::RegexSet;
+use std::fs::File;
+use std::hash::Hasher;
+use std::io::{self, ErrorKind, Read};
+use std::path::Path;
+
+use crate::walk::files_to_compare;
+use crate::{CompareOptions, Error};
+
+/// Chunks are never cut before this many bytes, except at the end of a file.
+const MIN_SIZE: usize = 2 * 1024;
+
+/// The size chunks are aimed at. Cut points get harder to hit below it and easier above it, which
+/// keeps the sizes close to it.
+const AVERAGE_SIZE: usize = 8 * 1024;
+
+/// Chunks are always cut after this many bytes.
+const MAX_SIZE: usize = 64 * 1024;
+
+/// Bits of the rolling hash that have to be zero for a cut below [`AVERAGE_SIZE`], two more than
+/// the 13 bits of an 8 KiB average. The top bits are used as they depend on the last 64 bytes.
+const MASK_SMALL: u64 = ((1 << 15) - 1) << 49;
+
+/// Bits of the rolling hash that have to be zero for a cut above [`AVERAGE_SIZE`], two less than
+/// the 13 bits of an 8 KiB average.
+const MASK_LARGE: u64 = ((1 << 11) - 1) << 53;
+
+/// Random values mixed into the rolling hash for every byte value, generated with SplitMix64 so
+/// the chunks are the same on every machine.
+const GEAR: [u64; 256] = gear_table();
+
+const fn gear_table() -> [u64; 256] {
+    let mut table = [0; 256];
+    let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+}
+
+/// A chunk of a file cut by content-defined chunking, see [`file_chunks`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Chunk {
+    /// The position of the first byte of the chunk within the file.
+    pub offset: u64,
+    /// The number of bytes in the chunk.
+    pub length: u64,
+    /// The hash of the content of the chunk, computed with `FxHasher`.
+    pub hash: u64,
+}
+
+/// Cuts the file at `path` into chunks at positions defined by its content, using a FastCDC style
+/// gear hash, and hashes every chunk. Inserting or removing bytes only changes the chunks around
+/// the edit, all other chunks keep their hashes, so chunks shared between files and versions of a
+/// file can be found by their hashes.
+///
+/// Chunks are between 2 KiB and 64 KiB large and 8 KiB on average, with cut points normalized
+/// towards the average. These parameters are fixed, so chunks of different runs and machines can
+/// be compared. As `FxHasher` depends on the word size, chunk hashes are only comparable between
+/// machines with the same pointer width, while the cut points are the same everywhere. Available with the
+/// `cdc` feature.
+///
+/// # Example
+///
+///```no_run
+/// use std::path::Path;
+/// use folder_compare::file_chunks;
+///
+/// for chunk in file_chunks(Path::new("/tmp/a/disk.img")).unwrap() {
+///     println!("{} bytes at {}: {:x}", chunk.length, chunk.offset, chunk.hash);
+/// }
+///```
+///
+pub fn file_chunks(path: &Path) -> Result<Vec<Chunk>, Error> {
+    Ok(chunks(File::open(path)?)?)
+}
+
+/// Sums up the sizes of the chunks of all files in `path1` whose content also shows up as a chunk
+/// of a file in `path2`, e.g. to estimate how much storing `path1` next to `path2` in a
+/// deduplicating backup would save. Every distinct chunk of `path1` is counted once. Files are cut
+/// into chunks like [`file_chunks`] does and everything matching the `excluded` patterns is left
+/// out. Available with the `cdc` feature.
+pub fn shared_chunk_bytes(path1: &Path, path2: &Path, excluded: &Vec<String>) -> Result<u64, Error> {
+    let set = RegexSet::new(excluded)?;
+    let options = CompareOptions::default();
+
+    let mut known: FxHashSet<u64> = FxHashSet::default();
+    for entry in files_to_compare(path2, &set, &options) {
+        known.extend(file_chunks(entry.path())?.into_iter().map(|chunk| chunk.hash));
+    }
+
+    let mut counted: FxHashSet<u64> = FxHashSet::default();
+    let mut shared = 0;
+    for entry in files_to_compare(path1, &set, &options) {
+        for chunk in file_chunks(entry.path())? {
+            if known.contains(&chunk.hash) && counted.insert(chunk.hash) {
+                shared += chunk.length;
+            }
+        }
+    }
+    Ok(shared)
+}
+
+/// Cuts everything readable from `reader` into chunks, see [`file_chunks`].
+fn chunks<R: Read>(mut reader: R) -> io::Result<Vec<Chunk>> {
+    let mut chunks = vec![];
+    let mut content = Vec::with_capacity(MAX_SIZE);
+    let mut offset = 0;
+    let mut fingerprint: u64 = 0;
+    let mut buffer = vec![0; MAX_SIZE];
+    loop {
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(ref error) if error.kind() == ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        };
+        for &byte in &buffer[..read] {
+            content.push(byte);
+            fingerprint = (fingerprint << 1).wrapping_add(GEAR[byte as usize]);
+            let mask = if content.len() < AVERAGE_SIZE { MASK_SMALL } else { MASK_LARGE };
+            if content.len() >= MAX_SIZE || (content.len() >= MIN_SIZE && fingerprint & mask == 0) {
+                offset = push_chunk(&mut chunks, &mut content, offset);
+                fingerprint = 0;
+            }
+        }
+    }
+    if !content.is_empty() {
+        push_chunk(&mut chunks, &mut content, offset);
+    }
+    Ok(chunks)
+}
+
+/// Hashes the `content` of a chunk starting at `offset`, adds it to `chunks` and empties
+/// `content` for the next one. Returns the offset of the next chunk.
+fn push_chunk(chunks: &mut Vec<Chunk>, content: &mut Vec<u8>, offset: u64) -> u64 {
+    let mut hasher = FxHasher::default();
+    hasher.write(content);
+    let length = content.len() as u64;
+    chunks.push(Chunk { offset, length, hash: hasher.finish() });
+    content.clear();
+    offset + length
+}
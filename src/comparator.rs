@@ -0,0 +1,1437 @@
+use fxhash::{FxHashMap, FxHashSet, FxHasher};
+use regex::bytes::Regex;
+use regex::RegexSet;
+use std::cmp::{Ordering, Reverse};
+use std::fmt;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::sync::atomic::Ordering as AtomicOrdering;
+use std::sync::{mpsc, Arc};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, UNIX_EPOCH};
+use unicode_normalization::UnicodeNormalization;
+
+use crate::hash::{differing_fraction, file_is_binary, ContentHasher};
+use crate::sink::{self, FileProgress, NoSink};
+use crate::walk::{counted_entries, counterpart_path, files_to_compare, is_excluded, is_special_type, skipped_symlinks, WalkCounts, WalkError};
+#[cfg(feature = "xattr-cache")]
+use crate::xattr_cache;
+use crate::{CompareMode, CompareOptions, Error, FileEntry, FileError, FileStatus, FolderCompare, OrderBy, ResultSink, Symlinks, WalkErrors};
+use crate::{times_match, MetadataDifference, DEFAULT_MTIME_GRANULARITY};
+
+/// A reusable comparison setup for running many comparisons with the same excluded patterns and
+/// options, e.g. in a watch loop.
+///
+/// The excluded patterns are compiled once and the buffer used for reading files is kept between
+/// comparisons. [`FolderCompare::new`] and [`FolderCompare::with_options`] use a temporary
+/// `Comparator` under the hood.
+///
+/// # Example
+///
+///```no_run
+/// use std::path::Path;
+/// use folder_compare::{Comparator, CompareOptions};
+///
+/// let mut comparator = Comparator::new(&vec![".txt".to_string()], CompareOptions::default()).unwrap();
+///
+/// for _ in 0..3 {
+///     let result = comparator.compare(Path::new("/tmp/a"), Path::new("/tmp/b")).unwrap();
+/// }
+///```
+///
+#[derive(Debug, Clone)]
+pub struct Comparator {
+    set1: RegexSet,
+    set2: RegexSet,
+    soft_set: RegexSet,
+    options: CompareOptions,
+    hasher: ContentHasher,
+}
+
+impl Comparator {
+    /// Compiles the `excluded` patterns and sets up a comparator using `options`. Fails with
+    /// [`Error::InvalidOption`] if an option is set to a value it can't take.
+    pub fn new(excluded: &Vec<String>, options: CompareOptions) -> Result<Self, Error> {
+        if options.change_threshold.is_some_and(|threshold| !(0.0..=1.0).contains(&threshold)) {
+            return Err(Error::InvalidOption("change_threshold"));
+        }
+        if options.shard.is_some_and(|(index, total)| index >= total) {
+            return Err(Error::InvalidOption("shard"));
+        }
+        Ok(Comparator {
+            set1: RegexSet::new(side_or_shared(&options.excluded_first, excluded))?,
+            set2: RegexSet::new(side_or_shared(&options.excluded_second, excluded))?,
+            soft_set: RegexSet::new(&options.soft_excluded)?,
+            hasher: ContentHasher::with_max_memory(options.max_memory)
+                .ignoring_lines(options.ignore_line_pattern.as_deref().map(Regex::new).transpose()?),
+            options,
+        })
+    }
+
+    /// The options used by this comparator.
+    pub fn options(&self) -> &CompareOptions {
+        &self.options
+    }
+
+    /// Compares `path1` with `path2`, see [`FolderCompare::with_options`].
+    pub fn compare(&mut self, path1: &Path, path2: &Path) -> Result<FolderCompare, Error> {
+        self.run(path1, path2, &mut |_| {})
+    }
+
+    /// Compares `path1` with `path2` like [`Comparator::compare`] does and passes every entry to
+    /// `sink` as soon as it is classified, e.g. for streaming the entries into a store of your own.
+    ///
+    /// The entries are only passed on, not collected, so memory doesn't grow with the number of
+    /// entries. Only [`CompareOptions::detect_name_only_differences`] and
+    /// [`CompareOptions::compare_hardlinks`] need the new, changed and unchanged entries once the
+    /// walk is done and keep them until the comparison is finished.
+    pub fn compare_into(&mut self, path1: &Path, path2: &Path, sink: &mut dyn ResultSink) -> Result<(), Error> {
+        let options = &self.options;
+        let collect = options.detect_name_only_differences || options.compare_hardlinks;
+        let mut on_read = |_| {};
+        let mut run = self.start(path1, path2, &mut on_read, sink, FolderCompare::default())?;
+        run.collect = collect;
+        run.compare()?;
+        Ok(())
+    }
+
+    /// Classifies the files at the given `relative` paths within `path1` and `path2` without
+    /// walking either directory, see [`FolderCompare::from_iter`].
+    pub fn compare_paths<I, P>(&mut self, path1: &Path, path2: &Path, relative: I) -> Result<FolderCompare, Error>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        self.start(path1, path2, &mut |_| {}, &mut NoSink, FolderCompare::default())?
+            .compare_listed(&mut relative.into_iter().map(|path| path.as_ref().to_path_buf()))
+    }
+
+    /// Compares `path1` with `path2` like [`Comparator::compare`] does, but stops at the first
+    /// new, changed or deleted entry and returns it, e.g. for a check failing fast. Partial files,
+    /// see [`CompareOptions::detect_partial`], are returned as changed. Returns `None`
+    /// if there is no difference, or none was found before reaching
+    /// [`CompareOptions::max_bytes_read`]. [`CompareOptions::limit`] is ignored, and so are the
+    /// passes over the whole result run after the walk, like
+    /// [`CompareOptions::detect_same_content`] or [`CompareOptions::report_skipped_symlinks`].
+    ///
+    /// # Example
+    ///
+    ///```no_run
+    /// use std::path::Path;
+    /// use folder_compare::{Comparator, CompareOptions};
+    ///
+    /// let mut comparator = Comparator::new(&vec![], CompareOptions::default()).unwrap();
+    /// if let Some((path, status)) = comparator.first_difference(Path::new("/tmp/a"), Path::new("/tmp/b")).unwrap() {
+    ///     println!("{} is {:?}", path.display(), status);
+    /// }
+    ///```
+    ///
+    pub fn first_difference(&mut self, path1: &Path, path2: &Path) -> Result<Option<(PathBuf, FileStatus)>, Error> {
+        let options = CompareOptions {
+            limit: Some(1),
+            detect_name_only_differences: false,
+            detect_same_content: false,
+            compare_hardlinks: false,
+            compare_content_sets: false,
+            report_skipped_symlinks: false,
+            ..self.options.clone()
+        };
+        let (mut on_read, mut sink) = (|_| {}, NoSink);
+        let mut run = self.start(path1, path2, &mut on_read, &mut sink, FolderCompare::default())?;
+        run.options = &options;
+        let result = run.compare()?;
+        Ok(result.new_files.into_iter().map(|path| (path, FileStatus::New))
+            .chain(result.changed_files.into_iter().map(|path| (path, FileStatus::Changed)))
+            .chain(result.partial.into_iter().map(|(path, _)| (path, FileStatus::Changed)))
+            .chain(result.deleted_files.into_iter().map(|path| (path, FileStatus::Deleted)))
+            .next())
+    }
+
+    /// Compares `path1` with `path2` and reports the progress in bytes, see
+    /// [`FolderCompare::compare_with_progress_bytes`].
+    pub fn compare_with_progress_bytes<F>(&mut self, path1: &Path, path2: &Path, mut progress: F) -> Result<FolderCompare, Error>
+    where
+        F: FnMut(u64, u64),
+    {
+        let total = self.estimate_bytes(path1, path2)?;
+        let mut read = 0;
+        self.run(path1, path2, &mut |bytes| {
+            read += bytes;
+            progress(read, total);
+        })
+    }
+
+    /// Compares `path1` with `path2` and reports the progress in entries, see
+    /// [`FolderCompare::compare_with_progress_files`].
+    pub fn compare_with_progress_files<F>(&mut self, path1: &Path, path2: &Path, progress: F) -> Result<FolderCompare, Error>
+    where
+        F: FnMut(u64, u64),
+    {
+        let total = self.count_entries(path1, path2)?;
+        self.run_with(path1, path2, &mut |_| {}, &mut FileProgress { done: 0, total, progress })
+    }
+
+    /// Compares `path1` with `path2` and reports the progress as a fraction, see
+    /// [`FolderCompare::compare_with_progress_fraction`].
+    pub fn compare_with_progress_fraction<F>(&mut self, path1: &Path, path2: &Path, mut progress: F) -> Result<FolderCompare, Error>
+    where
+        F: FnMut(f64),
+    {
+        self.compare_with_progress_files(path1, path2, |done, total| {
+            progress(if total == 0 { 1.0 } else { (done as f64 / total as f64).min(1.0) });
+        })
+    }
+
+    /// Counts the entries of `path1` that are going to be classified when comparing `path1` and
+    /// `path2`, along with the ones of `path2` missing in `path1` with
+    /// [`CompareOptions::detect_deleted`].
+    fn count_entries(&self, path1: &Path, path2: &Path) -> Result<u64, Error> {
+        let mut total = files_to_compare(path1, &self.set1, &self.options).count() as u64;
+        if self.options.detect_deleted {
+            for entry in files_to_compare(path2, &self.set2, &self.options) {
+                let counterpart = counterpart_path(path1, entry.path().strip_prefix(path2)?, &self.options);
+                if counterpart.symlink_metadata().is_err() || is_excluded(&counterpart, &self.set1) {
+                    total += 1;
+                }
+            }
+        }
+        Ok(total)
+    }
+
+    /// Sums up the sizes of all files that are going to be read when comparing `path1` and `path2`.
+    fn estimate_bytes(&self, path1: &Path, path2: &Path) -> Result<u64, Error> {
+        let mut total = 0;
+        for entry in files_to_compare(path1, &self.set1, &self.options).filter(|e| e.file_type().is_file()) {
+            let file_in_second_path = counterpart_path(path2, entry.path().strip_prefix(path1)?, &self.options);
+            if file_in_second_path.is_file() {
+                let size1 = entry.metadata().map(|m| m.len()).unwrap_or(0);
+                let size2 = file_in_second_path.metadata()?.len();
+                if !sizes_tell_apart(&self.options, size1, size2) {
+                    total += size1 + size2;
+                }
+            }
+        }
+        Ok(total)
+    }
+
+    /// Runs a single comparison. `on_read` is called with the number of bytes read for every chunk
+    /// hashed.
+    fn run(&mut self, path1: &Path, path2: &Path, on_read: &mut dyn FnMut(u64)) -> Result<FolderCompare, Error> {
+        self.run_with(path1, path2, on_read, &mut NoSink)
+    }
+
+    /// Compares `path1` with `path2` like [`Comparator::compare`] does, but fills `out` instead of
+    /// a new result, reusing the capacity of its lists. `out` is left empty if the comparison fails.
+    pub(crate) fn compare_reusing(&mut self, path1: &Path, path2: &Path, out: &mut FolderCompare) -> Result<(), Error> {
+        out.clear();
+        *out = self.run_into(path1, path2, &mut |_| {}, &mut NoSink, std::mem::take(out))?;
+        Ok(())
+    }
+
+    /// Runs a single comparison like [`Comparator::run`] does, additionally passing every entry to
+    /// `sink` as soon as it is classified.
+    fn run_with(&mut self, path1: &Path, path2: &Path, on_read: &mut dyn FnMut(u64), sink: &mut dyn ResultSink) -> Result<FolderCompare, Error> {
+        self.run_into(path1, path2, on_read, sink, FolderCompare::default())
+    }
+
+    /// Runs a single comparison like [`Comparator::run_with`] does, collecting the entries into
+    /// `result`, whose lists are expected to be empty.
+    fn run_into(&mut self, path1: &Path, path2: &Path, on_read: &mut dyn FnMut(u64), sink: &mut dyn ResultSink, result: FolderCompare) -> Result<FolderCompare, Error> {
+        self.start(path1, path2, on_read, sink, result)?.compare()
+    }
+
+    /// Sets up a single comparison of `path1` with `path2`, collecting the entries into `result`.
+    fn start<'a>(&'a mut self, path1: &'a Path, path2: &'a Path, on_read: &'a mut dyn FnMut(u64), sink: &'a mut dyn ResultSink, result: FolderCompare) -> Result<Run<'a>, Error> {
+        for root in [path1, path2] {
+            if !fs::metadata(root).is_ok_and(|m| m.is_dir()) {
+                return Err(Error::NotADirectory(root.to_path_buf()));
+            }
+        }
+        let roots = match self.options.confine_to_roots {
+            true => Some((path1.canonicalize()?, path2.canonicalize()?)),
+            false => None,
+        };
+        Ok(Run {
+            path1,
+            path2,
+            roots,
+            set1: &self.set1,
+            set2: &self.set2,
+            soft_set: &self.soft_set,
+            options: &self.options,
+            hasher: &mut self.hasher,
+            on_read,
+            bytes_read: 0,
+            differences: 0,
+            counts: Arc::default(),
+            hashes: FxHashMap::default(),
+            folded: None,
+            worker: None,
+            sink,
+            collect: true,
+            result: FolderCompare {
+                path1: path1.to_path_buf(),
+                path2: path2.to_path_buf(),
+                ..result
+            },
+        })
+    }
+}
+
+/// The state of a single comparison of `path1` with `path2`.
+struct Run<'a> {
+    path1: &'a Path,
+    path2: &'a Path,
+    /// Both directories with all symbolic links resolved, set with [`CompareOptions::confine_to_roots`].
+    roots: Option<(PathBuf, PathBuf)>,
+    set1: &'a RegexSet,
+    set2: &'a RegexSet,
+    soft_set: &'a RegexSet,
+    options: &'a CompareOptions,
+    hasher: &'a mut ContentHasher,
+    on_read: &'a mut dyn FnMut(u64),
+    /// The number of bytes read for hashing files so far, see [`CompareOptions::max_bytes_read`].
+    bytes_read: u64,
+    /// The number of new, changed and deleted entries recorded so far, see [`CompareOptions::limit`].
+    differences: usize,
+    /// The entries left out by the walks so far, see [`FolderCompare::excluded_files_count`].
+    counts: Arc<WalkCounts>,
+    /// The hashes of the files read so far by their inode, see [`CompareOptions::dedupe_reads`].
+    hashes: FxHashMap<(u64, u64), u64>,
+    /// The entries of both directories by their folded relative paths, see
+    /// [`CompareOptions::case_insensitive_paths`].
+    folded: Option<(FxHashMap<String, PathBuf>, FxHashMap<String, PathBuf>)>,
+    /// The thread hashing files for [`CompareOptions::per_file_timeout`], started with the first
+    /// file hashed and replaced once a file times out.
+    worker: Option<HashWorker>,
+    sink: &'a mut dyn ResultSink,
+    /// Whether the entries are collected into the lists of `result` besides passing them to
+    /// `sink`, see [`Comparator::compare_into`].
+    collect: bool,
+    result: FolderCompare,
+}
+
+impl<'a> Run<'a> {
+    /// Walks `path1` and classifies every file against its counterpart in `path2`.
+    fn compare(mut self) -> Result<FolderCompare, Error> {
+        if self.options.merge_join {
+            self.compare_lockstep()?;
+        } else {
+            self.compare_lookup()?;
+        }
+
+        if self.options.detect_name_only_differences && !self.result.truncated {
+            self.find_name_only_differences()?;
+        }
+        if self.options.detect_same_content && !self.result.truncated {
+            self.find_same_content()?;
+        }
+        if self.options.compare_hardlinks && !self.result.truncated {
+            self.find_hardlink_changes()?;
+        }
+        if self.options.compare_content_sets && !self.result.truncated {
+            self.find_content_set_changes()?;
+        }
+        if self.options.report_skipped_symlinks {
+            self.result.skipped_symlinks.extend(skipped_symlinks(self.path1, self.set1, self.options));
+            if self.options.detect_deleted {
+                self.result.skipped_symlinks.extend(skipped_symlinks(self.path2, self.set2, self.options));
+            }
+        }
+
+        self.result.excluded_files_count = self.counts.excluded_files.load(AtomicOrdering::Relaxed);
+        self.result.pruned_dirs_count = self.counts.pruned_dirs.load(AtomicOrdering::Relaxed);
+        self.result.symlinks_skipped = self.counts.skipped_symlinks.load(AtomicOrdering::Relaxed);
+        Ok(self.finish())
+    }
+
+    /// Classifies the files at the `relative` paths in `path1` against their counterparts in
+    /// `path2` without walking either directory. Paths of files missing or excluded on both sides
+    /// are skipped, and so are paths escaping either directory with
+    /// [`CompareOptions::confine_to_roots`] and paths listed before.
+    fn compare_listed(mut self, relative: &mut dyn Iterator<Item = PathBuf>) -> Result<FolderCompare, Error> {
+        let (path1, path2) = (self.path1, self.path2);
+        let mut listed: FxHashSet<PathBuf> = FxHashSet::default();
+        for relative in relative {
+            if self.limit_reached() {
+                break;
+            }
+            let file1 = counterpart_path(path1, &relative, self.options);
+            let file2 = counterpart_path(path2, &relative, self.options);
+            if !listed.insert(file1.clone()) {
+                continue;
+            }
+            if self.escapes_root(&file1, true)? || self.escapes_root(&file2, false)? {
+                continue;
+            }
+            let in_first = file1.is_file() && !is_excluded(&file1, self.set1);
+            let in_second = file2.is_file() && !is_excluded(&file2, self.set2);
+            match (in_first, in_second) {
+                (true, true) => self.classify_pair(file1, &file2)?,
+                (true, false) => {
+                    self.record(FileStatus::New, file1);
+                }
+                (false, true) if self.options.detect_deleted => {
+                    self.record(FileStatus::Deleted, file2);
+                }
+                _ => {}
+            }
+        }
+        Ok(self.finish())
+    }
+
+    /// Forms the path of the counterpart of the entry at the `relative` path in the other
+    /// directory, which is `path1` for entries of the second directory. With
+    /// [`CompareOptions::case_insensitive_paths`] the entry whose path only differs in case is
+    /// taken if there is one.
+    fn counterpart(&self, in_second: bool, relative: &Path) -> PathBuf {
+        let (root, folded) = match in_second {
+            true => (self.path1, self.folded.as_ref().map(|folded| &folded.0)),
+            false => (self.path2, self.folded.as_ref().map(|folded| &folded.1)),
+        };
+        match folded.and_then(|folded| folded.get(&fold_path(relative))) {
+            Some(path) => path.clone(),
+            None => counterpart_path(root, relative, self.options),
+        }
+    }
+
+    /// Orders the result and rebases its paths as set in the options.
+    fn finish(mut self) -> FolderCompare {
+        sort_result(&mut self.result, self.options.order_by);
+        if let Some(ref base) = self.options.display_base {
+            rebase(&mut self.result, base);
+        }
+        self.result
+    }
+
+    /// Walks `path1` and looks up the counterpart of every entry in `path2`.
+    fn compare_lookup(&mut self) -> Result<(), Error> {
+        let (path1, path2, options) = (self.path1, self.path2, self.options);
+        if options.case_insensitive_paths {
+            self.folded = Some((fold_paths(path1, self.set1, options)?, fold_paths(path2, self.set2, options)?));
+        }
+
+        for entry in counted_entries(path1, self.set1, options, self.counts.clone()) {
+            if self.limit_reached() {
+                break;
+            }
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(error) => {
+                    self.walk_failed(error)?;
+                    continue;
+                }
+            };
+
+            let path_without_prefix = entry.path().strip_prefix(path1)?;
+            let file_in_second_path = self.counterpart(false, path_without_prefix);
+            if self.escapes_root(&file_in_second_path, false)? {
+                continue;
+            }
+
+            if entry.file_type().is_dir() {
+                if file_in_second_path.is_dir() && !is_excluded(&file_in_second_path, self.set2) {
+                    self.record(FileStatus::Unchanged, entry.into_path());
+                } else {
+                    self.record(FileStatus::New, entry.into_path());
+                }
+                continue;
+            }
+
+            if entry.file_type().is_symlink() || (options.symlinks == Symlinks::CompareTargets && is_symlink(&file_in_second_path)) {
+                if file_in_second_path.symlink_metadata().is_ok() && !is_excluded(&file_in_second_path, self.set2) {
+                    self.classify_link(entry.into_path(), &file_in_second_path)?;
+                } else {
+                    self.record(FileStatus::New, entry.into_path());
+                }
+                continue;
+            }
+
+            if !entry.file_type().is_file() {
+                self.result.unexpected_entries.push(entry.into_path());
+                continue;
+            }
+
+            if !file_in_second_path.is_file() || is_excluded(&file_in_second_path, self.set2) {
+                if options.symlinks != Symlinks::Skip && is_broken_symlink(&file_in_second_path) {
+                    self.broken_symlink(file_in_second_path);
+                    continue;
+                }
+                if options.strict_regular && is_special(&file_in_second_path) {
+                    self.result.unexpected_entries.push(file_in_second_path);
+                    continue;
+                }
+                self.record(FileStatus::New, entry.into_path());
+                continue;
+            }
+
+            self.classify_pair(entry.into_path(), &file_in_second_path)?;
+        }
+
+        if options.detect_deleted {
+            for entry in counted_entries(path2, self.set2, options, self.counts.clone()) {
+                if self.limit_reached() {
+                    break;
+                }
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(error) => {
+                        self.walk_failed(error)?;
+                        continue;
+                    }
+                };
+                let path_without_prefix = entry.path().strip_prefix(path2)?;
+                let counterpart = self.counterpart(true, path_without_prefix);
+                if self.escapes_root(&counterpart, true)? {
+                    continue;
+                }
+                let excluded = is_excluded(&counterpart, self.set1);
+                if entry.file_type().is_dir() {
+                    if !counterpart.is_dir() || excluded {
+                        self.record(FileStatus::Deleted, entry.into_path());
+                    }
+                    continue;
+                }
+                if entry.file_type().is_symlink() || (options.symlinks == Symlinks::CompareTargets && is_symlink(&counterpart)) {
+                    if counterpart.symlink_metadata().is_err() || excluded {
+                        self.record(FileStatus::Deleted, entry.into_path());
+                    }
+                    continue;
+                }
+                if counterpart.is_file() && !excluded {
+                    continue;
+                }
+                if entry.file_type().is_file() {
+                    self.record(FileStatus::Deleted, entry.into_path());
+                } else {
+                    self.result.unexpected_entries.push(entry.into_path());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Walks `path1` and `path2` side by side, both sorted by file name, and classifies the entries
+    /// by merging the two sorted streams. Unlike [`Run::compare_lookup`] no lookups in the second
+    /// directory are needed and only the current entry of each walk is held in memory.
+    fn compare_lockstep(&mut self) -> Result<(), Error> {
+        let (path1, path2, options) = (self.path1, self.path2, self.options);
+        let mut first = counted_entries(path1, self.set1, options, self.counts.clone()).peekable();
+        let mut second = counted_entries(path2, self.set2, options, self.counts.clone()).peekable();
+
+        loop {
+            if let Some(Err(error)) = first.next_if(Result::is_err) {
+                self.walk_failed(error)?;
+                continue;
+            }
+            if let Some(Err(error)) = second.next_if(Result::is_err) {
+                self.walk_failed(error)?;
+                continue;
+            }
+
+            let order = match (first.peek(), second.peek()) {
+                (None, None) => break,
+                _ if self.limit_reached() => break,
+                (Some(Ok(a)), Some(Ok(b))) => a.path().strip_prefix(path1)?.cmp(b.path().strip_prefix(path2)?),
+                (Some(_), _) => Ordering::Less,
+                (None, _) => Ordering::Greater,
+            };
+
+            let (a, b) = match order {
+                Ordering::Less => (first.next(), None),
+                Ordering::Greater => (None, second.next()),
+                Ordering::Equal => (first.next(), second.next()),
+            };
+            let (a, b) = (a.and_then(Result::ok), b.and_then(Result::ok));
+
+            match (a, b) {
+                (Some(a), Some(b)) if a.file_type().is_file() && b.file_type().is_file() => {
+                    self.classify_pair(a.into_path(), b.path())?;
+                }
+                (Some(a), Some(b)) if a.file_type().is_dir() && b.file_type().is_dir() => {
+                    self.record(FileStatus::Unchanged, a.into_path());
+                }
+                (Some(a), Some(b)) if a.file_type().is_symlink() || b.file_type().is_symlink() => {
+                    self.classify_link(a.into_path(), b.path())?;
+                }
+                (a, b) => {
+                    if let Some(a) = a {
+                        if a.file_type().is_file() || a.file_type().is_dir() || a.file_type().is_symlink() {
+                            self.record(FileStatus::New, a.into_path());
+                        } else {
+                            self.result.unexpected_entries.push(a.into_path());
+                        }
+                    }
+                    if let Some(b) = b {
+                        if is_special_type(b.file_type()) {
+                            self.result.unexpected_entries.push(b.into_path());
+                        } else if options.detect_deleted {
+                            self.record(FileStatus::Deleted, b.into_path());
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Pairs new files with files only existing in the second directory, whose relative paths only
+    /// differ in case or Unicode normalization and whose content is identical.
+    fn find_name_only_differences(&mut self) -> Result<(), Error> {
+        let (path1, path2, options) = (self.path1, self.path2, self.options);
+
+        let mut only_in_second: FxHashMap<String, Vec<PathBuf>> = FxHashMap::default();
+        for entry in files_to_compare(path2, self.set2, options).filter(|e| e.file_type().is_file()) {
+            let path_without_prefix = entry.path().strip_prefix(path2)?;
+            if counterpart_path(path1, path_without_prefix, options).is_file() {
+                continue;
+            }
+            only_in_second.entry(fold_path(path_without_prefix)).or_default().push(entry.into_path());
+        }
+
+        let new_files: Vec<PathBuf> = self.result.new_files.iter().filter(|p| p.is_file()).cloned().collect();
+        for new_file in new_files {
+            let candidates = match only_in_second.get(&fold_path(new_file.strip_prefix(path1)?)) {
+                None => continue,
+                Some(candidates) => candidates,
+            };
+            let hash = self.hasher.hash_file(&new_file, self.on_read)?;
+            for candidate in candidates {
+                if self.hasher.hash_file(candidate, self.on_read)? == hash {
+                    self.result.name_only_differences.push((new_file.clone(), candidate.clone()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Hashes the file at `path` for matching files by their content regardless of their paths,
+    /// mixing its file name into the hash with [`CompareOptions::hash_file_names`].
+    fn content_key(&mut self, path: &Path) -> Result<u64, Error> {
+        let hash = self.hasher.hash_for_compare(path, self.options, self.on_read)?;
+        if !self.options.hash_file_names {
+            return Ok(hash);
+        }
+        let mut hasher = FxHasher::default();
+        hasher.write_u64(hash);
+        path.file_name().hash(&mut hasher);
+        Ok(hasher.finish())
+    }
+
+    /// Pairs files of both directories with identical content but different relative paths, using
+    /// an index of the hashes of all files in the second directory.
+    fn find_same_content(&mut self) -> Result<(), Error> {
+        let (path1, path2, options) = (self.path1, self.path2, self.options);
+
+        let mut by_hash: FxHashMap<u64, Vec<PathBuf>> = FxHashMap::default();
+        for entry in files_to_compare(path2, self.set2, options).filter(|e| e.file_type().is_file()) {
+            let hash = self.content_key(entry.path())?;
+            by_hash.entry(hash).or_default().push(entry.into_path());
+        }
+
+        for entry in files_to_compare(path1, self.set1, options).filter(|e| e.file_type().is_file()) {
+            let hash = self.content_key(entry.path())?;
+            let relative = entry.path().strip_prefix(path1)?;
+            for candidate in by_hash.get(&hash).into_iter().flatten() {
+                if candidate.strip_prefix(path2)? != relative {
+                    self.result.same_content_different_path.push((entry.path().to_path_buf(), candidate.clone()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Lists directories existing in both directories whose files directly within them have a
+    /// different multiset of content hashes, ignoring their names unless
+    /// [`CompareOptions::hash_file_names`] is set.
+    fn find_content_set_changes(&mut self) -> Result<(), Error> {
+        let (path1, path2, options) = (self.path1, self.path2, self.options);
+
+        let mut sets1: FxHashMap<PathBuf, Vec<u64>> = FxHashMap::default();
+        let mut sets2: FxHashMap<PathBuf, Vec<u64>> = FxHashMap::default();
+        for (root, set, hashes) in [(path1, self.set1, &mut sets1), (path2, self.set2, &mut sets2)] {
+            for entry in files_to_compare(root, set, options).filter(|e| e.file_type().is_file()) {
+                let dir = entry.path().parent().unwrap_or(root).strip_prefix(root)?.to_path_buf();
+                hashes.entry(dir).or_default().push(self.content_key(entry.path())?);
+            }
+        }
+        sets1.values_mut().chain(sets2.values_mut()).for_each(|hashes| hashes.sort_unstable());
+
+        let mut dirs: Vec<&PathBuf> = sets1.keys().chain(sets2.keys().filter(|dir| !sets1.contains_key(*dir))).collect();
+        dirs.sort();
+        let empty = vec![];
+        for dir in dirs {
+            let (dir1, dir2) = (counterpart_path(path1, dir, options), counterpart_path(path2, dir, options));
+            if dir1.is_dir() && dir2.is_dir() && sets1.get(dir).unwrap_or(&empty) != sets2.get(dir).unwrap_or(&empty) {
+                self.result.content_set_changed.push(dir1);
+            }
+        }
+        Ok(())
+    }
+
+    /// Lists files existing in both directories which are hard linked with a different set of the
+    /// compared files in each directory.
+    fn find_hardlink_changes(&mut self) -> Result<(), Error> {
+        let (path1, path2, options) = (self.path1, self.path2, self.options);
+
+        let mut groups1: FxHashMap<(u64, u64), Vec<&Path>> = FxHashMap::default();
+        let mut groups2: FxHashMap<(u64, u64), Vec<&Path>> = FxHashMap::default();
+        let mut links = vec![];
+        for file in self.result.changed_files.iter().chain(self.result.unchanged_files.iter()).filter(|p| p.is_file()) {
+            let relative = file.strip_prefix(path1)?;
+            if let (Some(inode1), Some(inode2)) = (inode(file), inode(&counterpart_path(path2, relative, options))) {
+                groups1.entry(inode1).or_default().push(relative);
+                groups2.entry(inode2).or_default().push(relative);
+                links.push((file, inode1, inode2));
+            }
+        }
+
+        let changed: Vec<PathBuf> = links.into_iter()
+            .filter(|(_, inode1, inode2)| groups1[inode1] != groups2[inode2])
+            .map(|(file, _, _)| file.clone())
+            .collect();
+        self.result.hardlink_structure_changed.extend(changed);
+        Ok(())
+    }
+
+    /// Compares the file at `file1` with its counterpart at `file2` and adds it to the matching list.
+    fn classify_pair(&mut self, file1: PathBuf, file2: &Path) -> Result<(), Error> {
+        if self.options.compare_ownership && ownership_differs(&file1, file2) {
+            self.result.ownership_changed.push(file1.clone());
+        }
+        if self.options.compare_permissions && permissions_differ(&file1, file2, self.options.permission_mask.unwrap_or(0o7777)) {
+            self.result.permissions_changed.push(file1.clone());
+        }
+
+        if is_excluded(&file1, self.soft_set) {
+            self.record_created(FileStatus::Unchanged, file1, file2);
+            return Ok(());
+        }
+
+        if self.options.empty_files_match {
+            if let (Ok(size1), Ok(size2)) = (file_size(&file1), file_size(file2)) {
+                if size1 == 0 || size2 == 0 {
+                    let status = if size1 == size2 { FileStatus::Unchanged } else { FileStatus::Changed };
+                    self.record_pair(status, file1, file2);
+                    return Ok(());
+                }
+            }
+        }
+
+        if self.options.detect_partial {
+            let size1 = match self.or_record_error(&file1, file_size(&file1))? {
+                Some(size) => size,
+                None => return Ok(()),
+            };
+            let size2 = match self.or_record_error(file2, file_size(file2))? {
+                Some(size) => size,
+                None => return Ok(()),
+            };
+            if size2 < size1 {
+                let prefix = self.hasher.is_prefix_of(file2, &file1).map_err(Error::from);
+                match self.or_record_error(&file1, prefix)? {
+                    Some(true) => {
+                        self.record_partial(file1, size1 - size2);
+                        return Ok(());
+                    }
+                    Some(false) => {}
+                    None => return Ok(()),
+                }
+            }
+        }
+
+        if self.options.mode == CompareMode::Size {
+            let status = if file_size(&file1)? == file_size(file2)? { FileStatus::Unchanged } else { FileStatus::Changed };
+            self.record_pair(status, file1, file2);
+            return Ok(());
+        }
+
+        if self.options.mode == CompareMode::Newer {
+            let granularity = self.options.mtime_granularity.unwrap_or(DEFAULT_MTIME_GRANULARITY);
+            let status = if modified_later(&file1, file2, granularity)? { FileStatus::Changed } else { FileStatus::Unchanged };
+            self.record_pair(status, file1, file2);
+            return Ok(());
+        }
+
+        if self.options.dedupe_reads && inode(&file1).is_some_and(|inode1| inode(file2) == Some(inode1)) {
+            self.record_pair(FileStatus::Unchanged, file1, file2);
+            return Ok(());
+        }
+
+        let hashes = match (file_size(&file1), file_size(file2)) {
+            (Ok(size1), Ok(size2)) if sizes_tell_apart(self.options, size1, size2) => None,
+            _ => {
+                let hash1 = match self.hash_or_record_error(&file1, true)? {
+                    Some(hash) => hash,
+                    None => return Ok(()),
+                };
+                let hash2 = match self.hash_or_record_error(file2, false)? {
+                    Some(hash) => hash,
+                    None => return Ok(()),
+                };
+                Some((hash1, hash2))
+            }
+        };
+        let mut status = match hashes {
+            Some((hash1, hash2)) if hash1 == hash2 => FileStatus::Unchanged,
+            _ => FileStatus::Changed,
+        };
+        if let (FileStatus::Changed, Some(threshold)) = (status, self.options.change_threshold) {
+            let fraction = self.differing_fraction(&file1, file2);
+            match self.or_record_error(&file1, fraction)? {
+                Some(fraction) if fraction <= threshold => status = FileStatus::Unchanged,
+                Some(_) => {}
+                None => return Ok(()),
+            }
+        }
+        if self.record_pair(status, file1.clone(), file2) == FileStatus::Changed && self.collect {
+            if let Some((hash1, hash2)) = hashes {
+                self.result.changed_hashes.push((file1, hash1, hash2));
+            }
+        }
+        Ok(())
+    }
+
+    /// Compares the symbolic link at `link1` with its counterpart at `link2` by their targets, as
+    /// set with [`Symlinks::CompareTargets`]. A counterpart that isn't a link is changed.
+    fn classify_link(&mut self, link1: PathBuf, link2: &Path) -> Result<(), Error> {
+        let status = if is_symlink(&link1) && is_symlink(link2) && fs::read_link(&link1)? == fs::read_link(link2)? {
+            FileStatus::Unchanged
+        } else {
+            FileStatus::Changed
+        };
+        self.record(status, link1);
+        Ok(())
+    }
+
+    /// Records the file at `file1` like [`Run::record_created`] does. With
+    /// [`CompareOptions::strict_metadata`], unchanged files whose metadata differs from the one of
+    /// their counterpart at `file2` are recorded as changed instead.
+    fn record_pair(&mut self, mut status: FileStatus, file1: PathBuf, file2: &Path) -> FileStatus {
+        if status == FileStatus::Unchanged && self.options.strict_metadata {
+            let differences = metadata_differences(&file1, file2, self.options);
+            if !differences.is_empty() {
+                status = FileStatus::Changed;
+                self.result.metadata_differences.extend(differences.into_iter().map(|difference| (file1.clone(), difference)));
+            }
+        }
+        self.record_created(status, file1, file2)
+    }
+
+    /// Records the file at `file1` like [`Run::record`] does and, if it ends up unchanged, checks
+    /// whether its creation time differs from the one of its counterpart at `file2`. Soft excluded
+    /// files are recorded this way directly, so metadata never marks them as changed.
+    fn record_created(&mut self, status: FileStatus, file1: PathBuf, file2: &Path) -> FileStatus {
+        let status = self.record(status, file1.clone());
+        if status == FileStatus::Unchanged && self.options.compare_created && created_differs(&file1, file2, self.options.mtime_granularity.unwrap_or(DEFAULT_MTIME_GRANULARITY)) {
+            self.result.created_changed.push(file1);
+        }
+        status
+    }
+
+    /// Checks whether `path`, the counterpart of an entry looked up in the second directory or, with
+    /// `in_first`, in the first one, resolves to a path outside of that directory with
+    /// [`CompareOptions::confine_to_roots`]. Escaping paths are recorded as errors with
+    /// [`CompareOptions::continue_on_error`] and fail the comparison otherwise.
+    fn escapes_root(&mut self, path: &Path, in_first: bool) -> Result<bool, Error> {
+        let root = match self.roots {
+            None => return Ok(false),
+            Some((ref first, _)) if in_first => first,
+            Some((_, ref second)) => second,
+        };
+        match path.canonicalize() {
+            Ok(resolved) if !resolved.starts_with(root) => {}
+            _ => return Ok(false),
+        }
+        let error = io::Error::new(io::ErrorKind::PermissionDenied, "path escapes the compared directory");
+        if !self.options.continue_on_error {
+            return Err(error.into());
+        }
+        let error = FileError::new(path, &error);
+        self.sink.on_error(&error);
+        self.result.errors.push(error);
+        Ok(true)
+    }
+
+    /// Hashes the file at `path` for the comparison. With [`CompareOptions::continue_on_error`] a
+    /// file that can't be read is listed in [`FolderCompare::errors`] and `None` is returned.
+    /// A file whose hashing exceeds [`CompareOptions::per_file_timeout`] is listed in
+    /// [`FolderCompare::timed_out`] and `None` is returned as well. `in_first` tells whether the
+    /// file is one of the first directory.
+    fn hash_or_record_error(&mut self, path: &Path, in_first: bool) -> Result<Option<u64>, Error> {
+        let inode = if self.options.dedupe_reads { inode(path) } else { None };
+        if let Some(hash) = inode.and_then(|inode| self.hashes.get(&inode)) {
+            return Ok(Some(*hash));
+        }
+        match self.hash_cached(path, in_first) {
+            Ok(hash) => {
+                if let Some(inode) = inode {
+                    self.hashes.insert(inode, hash);
+                }
+                Ok(Some(hash))
+            }
+            Err(Error::Io(ref error)) if error.get_ref().is_some_and(|inner| inner.is::<HashTimedOut>()) => {
+                self.result.timed_out.push(path.to_path_buf());
+                Ok(None)
+            }
+            Err(error) => self.or_record_error(path, Err(error)),
+        }
+    }
+
+    /// Passes on the `result` of reading the file at `path`. With
+    /// [`CompareOptions::continue_on_error`] an I/O error is listed in [`FolderCompare::errors`]
+    /// instead and `None` is returned.
+    fn or_record_error<T>(&mut self, path: &Path, result: Result<T, Error>) -> Result<Option<T>, Error> {
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(Error::Io(error)) if self.options.continue_on_error => {
+                let error = FileError::new(path, &error);
+                self.sink.on_error(&error);
+                self.result.errors.push(error);
+                Ok(None)
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Hashes the file at `path` for the comparison, using the hash cached in an extended attribute
+    /// with [`CompareOptions::use_xattr_cache`]. Hashes are only cached for files of the first
+    /// directory, as told by `in_first`, and only if the file wasn't modified while hashing it.
+    #[cfg(feature = "xattr-cache")]
+    fn hash_cached(&mut self, path: &Path, in_first: bool) -> Result<u64, Error> {
+        let options = self.options;
+        if !options.use_xattr_cache || options.ignore_case || options.ignore_trailing_nulls || options.sample_offsets.is_some() || options.ignore_line_pattern.is_some() {
+            return self.hash_with_retries(path);
+        }
+        #[cfg(feature = "blake3")]
+        {
+            if options.parallel_hash_threshold.is_some() {
+                return self.hash_with_retries(path);
+            }
+        }
+        let modified = match xattr_cache::modified_stamp(path) {
+            Some(modified) => modified,
+            None => return self.hash_with_retries(path),
+        };
+        if let Some(hash) = xattr_cache::cached_hash(path, &modified) {
+            return Ok(hash);
+        }
+        let hash = self.hash_with_retries(path)?;
+        if in_first {
+            xattr_cache::store_hash(path, &modified, hash);
+        }
+        Ok(hash)
+    }
+
+    #[cfg(not(feature = "xattr-cache"))]
+    fn hash_cached(&mut self, path: &Path, _in_first: bool) -> Result<u64, Error> {
+        self.hash_with_retries(path)
+    }
+
+    /// Hashes the file at `path` for the comparison, retrying up to [`CompareOptions::io_retries`]
+    /// times on transient errors. With [`CompareOptions::per_file_timeout`] the file is hashed on a
+    /// worker thread shared by all files of the comparison. A worker still reading once the timeout
+    /// passes is abandoned along with its read, which can't be cancelled, and the next file gets a
+    /// new one. A worker that ended without a result fails the file with an error of its own
+    /// rather than a timeout.
+    fn hash_with_retries(&mut self, path: &Path) -> Result<u64, Error> {
+        let (bytes_read, on_read) = (&mut self.bytes_read, &mut *self.on_read);
+        let on_read = &mut |bytes| {
+            *bytes_read += bytes;
+            on_read(bytes);
+        };
+        let timeout = match self.options.per_file_timeout {
+            None => return hash_retrying(self.hasher, path, self.options, on_read),
+            Some(timeout) => timeout,
+        };
+
+        let (hasher, options) = (&*self.hasher, self.options);
+        let worker = self.worker.get_or_insert_with(|| HashWorker::spawn(hasher.clone(), options.clone()));
+        let result = worker.hash(path, timeout);
+        if result.is_err() {
+            self.worker = None;
+        }
+        match result {
+            Ok(Ok((hash, read))) => {
+                if read > 0 {
+                    on_read(read);
+                }
+                Ok(hash)
+            }
+            Ok(Err(error)) => Err(error),
+            Err(mpsc::RecvTimeoutError::Timeout) => Err(io::Error::new(io::ErrorKind::TimedOut, HashTimedOut).into()),
+            Err(mpsc::RecvTimeoutError::Disconnected) => Err(io::Error::other(HashWorkerFailed).into()),
+        }
+    }
+
+    /// Compares the files at `file1` and `file2` block by block for
+    /// [`CompareOptions::change_threshold`], counting the bytes read like hashing does.
+    fn differing_fraction(&mut self, file1: &Path, file2: &Path) -> Result<f64, Error> {
+        let (bytes_read, on_read) = (&mut self.bytes_read, &mut *self.on_read);
+        Ok(differing_fraction(file1, file2, &mut |bytes| {
+            *bytes_read += bytes;
+            on_read(bytes);
+        })?)
+    }
+
+    /// Handles an entry that couldn't be read while walking one of the directories as set with
+    /// [`CompareOptions::walk_errors`]. Symbolic links whose target doesn't exist end up here when
+    /// following links and are listed as broken instead.
+    fn walk_failed(&mut self, error: WalkError) -> Result<(), Error> {
+        if let Some(path) = error.path() {
+            if is_broken_symlink(path) {
+                self.broken_symlink(path.to_path_buf());
+                return Ok(());
+            }
+        }
+        match self.options.walk_errors {
+            WalkErrors::Skip => Ok(()),
+            WalkErrors::Record => {
+                let error = FileError::new(error.path().unwrap_or(self.path1), error.error());
+                self.sink.on_walk_error(&error);
+                self.result.errors.push(error);
+                Ok(())
+            }
+            WalkErrors::Abort => Err(io::Error::from(error).into()),
+        }
+    }
+
+    /// Lists the broken symbolic link at `path`, unless it is listed already. A link in the second
+    /// directory is found both as the counterpart of a file and by the walk looking for deleted
+    /// files.
+    fn broken_symlink(&mut self, path: PathBuf) {
+        if !self.result.broken_symlinks.contains(&path) {
+            self.result.broken_symlinks.push(path);
+        }
+    }
+
+    /// Checks whether [`CompareOptions::limit`] differences were found or
+    /// [`CompareOptions::max_bytes_read`] bytes were read and flags the result as truncated if so.
+    fn limit_reached(&mut self) -> bool {
+        let result = &mut self.result;
+        if let Some(limit) = self.options.limit {
+            if self.differences >= limit {
+                result.truncated = true;
+            }
+        }
+        if let Some(max_bytes_read) = self.options.max_bytes_read {
+            if self.bytes_read >= max_bytes_read {
+                result.truncated = true;
+            }
+        }
+        result.truncated
+    }
+
+    /// Lists the file at `path`, whose counterpart lacks the last `missing` bytes of its content, as
+    /// partial and passes it to the sink. It counts as a difference for [`CompareOptions::limit`],
+    /// but isn't passed to [`CompareOptions::reclassify`] and has no metadata collected.
+    fn record_partial(&mut self, path: PathBuf, missing: u64) {
+        self.sink.on_partial(&path, missing);
+        self.differences += 1;
+        if self.collect {
+            self.result.partial.push((path, missing));
+        }
+    }
+
+    /// Adds `path` to the list matching its `status`, after giving [`CompareOptions::reclassify`] a
+    /// chance to change it, and returns the final status. The entry is also passed to the sink
+    /// right away, and only passed there unless the entries are collected. New entries are dropped
+    /// with [`CompareOptions::changes_only`].
+    fn record(&mut self, status: FileStatus, path: PathBuf) -> FileStatus {
+        if self.options.changes_only && status == FileStatus::New {
+            return status;
+        }
+        let status = match self.options.reclassify {
+            Some(ref reclassify) => reclassify.call(&path, status),
+            None => status,
+        };
+        sink::notify(self.sink, status, &path);
+        if self.options.collect_metadata {
+            let metadata = std::fs::metadata(&path).ok();
+            self.result.entries.push(FileEntry {
+                path: path.clone(),
+                status,
+                size: metadata.as_ref().map_or(0, |m| m.len()),
+                modified: metadata.and_then(|m| m.modified().ok()),
+            });
+        }
+        if self.options.detect_binary && status != FileStatus::Unchanged && path.is_file() && file_is_binary(&path) {
+            self.result.binary_files.push(path.clone());
+        }
+        if status != FileStatus::Unchanged {
+            self.differences += 1;
+        }
+        if !self.collect {
+            return status;
+        }
+        match status {
+            FileStatus::New => self.result.new_files.push(path),
+            FileStatus::Changed => self.result.changed_files.push(path),
+            FileStatus::Unchanged => self.result.unchanged_files.push(path),
+            FileStatus::Deleted => self.result.deleted_files.push(path),
+        }
+        status
+    }
+}
+
+/// Checks whether the files at `a` and `b` are equal using the same rules as the directory
+/// comparison does with the default options and the given `mode`. Comparing by content, files of
+/// different sizes are known to differ without reading them unless one of them is reported as
+/// empty, otherwise both are hashed chunk by chunk. With
+/// [`CompareMode::Newer`], `a` equals `b` unless it was modified later, by at least the default
+/// [`CompareOptions::mtime_granularity`].
+///
+/// # Example
+///
+///```no_run
+/// use std::path::Path;
+/// use folder_compare::{files_equal, CompareMode};
+///
+/// let equal = files_equal(Path::new("/tmp/a/test.txt"), Path::new("/tmp/b/test.txt"), CompareMode::Content).unwrap();
+///```
+///
+pub fn files_equal(a: &Path, b: &Path, mode: CompareMode) -> Result<bool, Error> {
+    if mode == CompareMode::Newer {
+        return Ok(!modified_later(a, b, DEFAULT_MTIME_GRANULARITY)?);
+    }
+    let (size1, size2) = (file_size(a)?, file_size(b)?);
+    if mode == CompareMode::Size || sizes_tell_apart(&CompareOptions::default(), size1, size2) {
+        return Ok(size1 == size2);
+    }
+
+    let mut hasher = ContentHasher::new();
+    Ok(hasher.hash_file(a, &mut |_| {})? == hasher.hash_file(b, &mut |_| {})?)
+}
+
+/// Pairs every file in `path1` with every file in `path2` that has byte-identical content,
+/// regardless of their paths within both directories. Everything matching the `excluded` patterns
+/// is left out. Files are grouped by size first, so only files with a counterpart of the same size
+/// are hashed at all.
+///
+/// # Example
+///
+///```no_run
+/// use std::path::Path;
+/// use folder_compare::identical_files;
+///
+/// for (first, second) in identical_files(Path::new("/tmp/a"), Path::new("/tmp/b"), &vec![]).unwrap() {
+///     println!("{} is a copy of {}", first.display(), second.display());
+/// }
+///```
+///
+pub fn identical_files(path1: &Path, path2: &Path, excluded: &Vec<String>) -> Result<Vec<(PathBuf, PathBuf)>, Error> {
+    let set = RegexSet::new(excluded)?;
+    let options = CompareOptions::default();
+
+    let mut by_size: FxHashMap<u64, Vec<PathBuf>> = FxHashMap::default();
+    for entry in files_to_compare(path2, &set, &options) {
+        by_size.entry(file_size(entry.path())?).or_default().push(entry.into_path());
+    }
+
+    let mut hasher = ContentHasher::new();
+    let mut hashes: FxHashMap<PathBuf, u64> = FxHashMap::default();
+    let mut identical = vec![];
+    for entry in files_to_compare(path1, &set, &options) {
+        let candidates = match by_size.get(&file_size(entry.path())?) {
+            None => continue,
+            Some(candidates) => candidates,
+        };
+        let hash = hasher.hash_file(entry.path(), &mut |_| {})?;
+        for candidate in candidates {
+            let candidate_hash = match hashes.get(candidate) {
+                Some(candidate_hash) => *candidate_hash,
+                None => {
+                    let candidate_hash = hasher.hash_file(candidate, &mut |_| {})?;
+                    hashes.insert(candidate.clone(), candidate_hash);
+                    candidate_hash
+                }
+            };
+            if candidate_hash == hash {
+                identical.push((entry.path().to_path_buf(), candidate.clone()));
+            }
+        }
+    }
+    Ok(identical)
+}
+
+/// Picks the excluded patterns of one side of the comparison, which are the `shared` ones unless
+/// patterns specific to that `side` are given.
+fn side_or_shared<'a>(side: &'a Vec<String>, shared: &'a Vec<String>) -> &'a Vec<String> {
+    if side.is_empty() { shared } else { side }
+}
+
+/// Reads the size of the file at `path` from its metadata.
+fn file_size(path: &Path) -> Result<u64, Error> {
+    Ok(std::fs::metadata(path)?.len())
+}
+
+/// Sorts the new, changed, unchanged and deleted files of `result` as set with
+/// [`CompareOptions::order_by`].
+fn sort_result(result: &mut FolderCompare, order_by: OrderBy) {
+    for paths in [
+        &mut result.new_files,
+        &mut result.changed_files,
+        &mut result.unchanged_files,
+        &mut result.deleted_files,
+    ] {
+        match order_by {
+            OrderBy::Walk => {}
+            OrderBy::PathAsc => paths.sort(),
+            OrderBy::SizeDesc => paths.sort_by_cached_key(|path| Reverse(path.metadata().map(|m| m.len()).unwrap_or(0))),
+            OrderBy::MtimeDesc => paths.sort_by_cached_key(|path| Reverse(path.metadata().and_then(|m| m.modified()).unwrap_or(UNIX_EPOCH))),
+        }
+    }
+}
+
+/// Rewrites all paths stored in `result` relative to `base`. Paths outside of `base` are kept as
+/// they are.
+fn rebase(result: &mut FolderCompare, base: &Path) {
+    let rebase = |path: &mut PathBuf| {
+        if let Ok(relative) = path.strip_prefix(base) {
+            *path = relative.to_path_buf();
+        }
+    };
+
+    rebase(&mut result.path1);
+    rebase(&mut result.path2);
+    for paths in [
+        &mut result.changed_files,
+        &mut result.new_files,
+        &mut result.unchanged_files,
+        &mut result.deleted_files,
+        &mut result.unexpected_entries,
+        &mut result.ownership_changed,
+        &mut result.permissions_changed,
+        &mut result.created_changed,
+        &mut result.hardlink_structure_changed,
+        &mut result.content_set_changed,
+        &mut result.binary_files,
+        &mut result.broken_symlinks,
+        &mut result.skipped_symlinks,
+        &mut result.timed_out,
+    ] {
+        paths.iter_mut().for_each(rebase);
+    }
+    for (first, second) in result.name_only_differences.iter_mut().chain(result.same_content_different_path.iter_mut()) {
+        rebase(first);
+        rebase(second);
+    }
+    result.changed_hashes.iter_mut().for_each(|(path, _, _)| rebase(path));
+    result.metadata_differences.iter_mut().for_each(|(path, _)| rebase(path));
+    result.partial.iter_mut().for_each(|(path, _)| rebase(path));
+    result.entries.iter_mut().for_each(|entry| rebase(&mut entry.path));
+    result.errors.iter_mut().for_each(|error| rebase(&mut error.path));
+}
+
+/// Hashes the file at `path` with `hasher` for the comparison, retrying up to
+/// [`CompareOptions::io_retries`] times on transient errors. The delay before each retry doubles,
+/// starting at 10 ms.
+fn hash_retrying(hasher: &mut ContentHasher, path: &Path, options: &CompareOptions, on_read: &mut dyn FnMut(u64)) -> Result<u64, Error> {
+    let mut delay = Duration::from_millis(10);
+    for _ in 0..options.io_retries {
+        match hasher.hash_for_compare(path, options, on_read) {
+            Err(Error::Io(ref error)) if is_transient(error.kind()) => {
+                thread::sleep(delay);
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+    hasher.hash_for_compare(path, options, on_read)
+}
+
+/// A thread hashing the files sent to it one after another, see [`CompareOptions::per_file_timeout`].
+struct HashWorker {
+    paths: mpsc::Sender<PathBuf>,
+    results: mpsc::Receiver<Result<(u64, u64), Error>>,
+}
+
+impl HashWorker {
+    /// Starts a thread hashing files with `hasher` and `options`, which ends once the worker is
+    /// dropped and the file it reads, if any, is done.
+    fn spawn(mut hasher: ContentHasher, options: CompareOptions) -> Self {
+        let (paths, jobs) = mpsc::channel::<PathBuf>();
+        let (sender, results) = mpsc::channel();
+        thread::spawn(move || {
+            for path in jobs {
+                let mut read = 0;
+                let result = hash_retrying(&mut hasher, &path, &options, &mut |bytes| read += bytes);
+                if sender.send(result.map(|hash| (hash, read))).is_err() {
+                    break;
+                }
+            }
+        });
+        HashWorker { paths, results }
+    }
+
+    /// Hashes the file at `path` on the worker thread and returns its hash along with the number
+    /// of bytes read. Fails with `Timeout` if that takes longer than `timeout` and with
+    /// `Disconnected` if the thread ended, e.g. by panicking. The worker can't be used after an
+    /// error, as it may still be busy with the file.
+    fn hash(&self, path: &Path, timeout: Duration) -> Result<Result<(u64, u64), Error>, mpsc::RecvTimeoutError> {
+        if self.paths.send(path.to_path_buf()).is_err() {
+            return Err(mpsc::RecvTimeoutError::Disconnected);
+        }
+        self.results.recv_timeout(timeout)
+    }
+}
+
+/// The error hashing a file fails with once it takes longer than
+/// [`CompareOptions::per_file_timeout`].
+#[derive(Debug)]
+struct HashTimedOut;
+
+impl fmt::Display for HashTimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("hashing the file timed out")
+    }
+}
+
+impl std::error::Error for HashTimedOut {}
+
+/// The error hashing a file fails with if the thread hashing it for
+/// [`CompareOptions::per_file_timeout`] ended without a result, i.e. panicked.
+#[derive(Debug)]
+struct HashWorkerFailed;
+
+impl fmt::Display for HashWorkerFailed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("the thread hashing the file failed")
+    }
+}
+
+impl std::error::Error for HashWorkerFailed {}
+
+/// Checks whether an I/O error of the given `kind` may go away when trying again.
+fn is_transient(kind: io::ErrorKind) -> bool {
+    matches!(kind, io::ErrorKind::Interrupted | io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock)
+}
+
+/// Checks whether `path` is a symbolic link, no matter whether its target exists.
+fn is_symlink(path: &Path) -> bool {
+    path.symlink_metadata().map(|m| m.file_type().is_symlink()).unwrap_or(false)
+}
+
+/// Checks whether `path` is a symbolic link pointing to something that doesn't exist.
+fn is_broken_symlink(path: &Path) -> bool {
+    is_symlink(path) && path.metadata().is_err()
+}
+
+/// Checks whether something exists at `path` that is neither a regular file nor a directory.
+fn is_special(path: &Path) -> bool {
+    std::fs::metadata(path).map(|m| is_special_type(m.file_type())).unwrap_or(false)
+}
+
+/// Folds a relative path into a key that is equal for paths only differing in case or Unicode
+/// normalization.
+fn fold_path(relative: &Path) -> String {
+    relative.to_string_lossy().nfc().collect::<String>().to_lowercase()
+}
+
+/// Indexes the entries of the directory `root` by their relative paths folded with [`fold_path`],
+/// failing with [`Error::CaseCollision`] if two of them fold to the same key.
+fn fold_paths(root: &Path, set: &RegexSet, options: &CompareOptions) -> Result<FxHashMap<String, PathBuf>, Error> {
+    let mut folded: FxHashMap<String, PathBuf> = FxHashMap::default();
+    for entry in files_to_compare(root, set, options) {
+        let key = fold_path(entry.path().strip_prefix(root)?);
+        if let Some(other) = folded.insert(key, entry.path().to_path_buf()) {
+            return Err(Error::CaseCollision(other, entry.into_path()));
+        }
+    }
+    Ok(folded)
+}
+
+/// Checks whether the files at `path1` and `path2` have a different owner or group. Files whose
+/// metadata can't be read are treated as having the same owner.
+#[cfg(unix)]
+fn ownership_differs(path1: &Path, path2: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (std::fs::metadata(path1), std::fs::metadata(path2)) {
+        (Ok(m1), Ok(m2)) => m1.uid() != m2.uid() || m1.gid() != m2.gid(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn ownership_differs(_path1: &Path, _path2: &Path) -> bool {
+    false
+}
+
+/// Identifies the file at `path` by its device and inode number, so hard links to the same file
+/// share the same identity. Only available on Unix.
+#[cfg(unix)]
+fn inode(path: &Path) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    std::fs::metadata(path).ok().map(|m| (m.dev(), m.ino()))
+}
+
+#[cfg(not(unix))]
+fn inode(_path: &Path) -> Option<(u64, u64)> {
+    None
+}
+
+/// Checks whether files of `size1` and `size2` bytes are known to differ in content without
+/// reading them, which is the case for different sizes unless one of them is reported as empty,
+/// see [`CompareMode::Content`], or `options` compare content of different sizes as equal.
+fn sizes_tell_apart(options: &CompareOptions, size1: u64, size2: u64) -> bool {
+    size1 != size2 && size1 != 0 && size2 != 0 && !options.ignore_trailing_nulls && options.ignore_line_pattern.is_none()
+}
+
+/// Checks whether the file at `path1` was modified later than the one at `path2`, by at least
+/// `granularity`.
+fn modified_later(path1: &Path, path2: &Path, granularity: Duration) -> io::Result<bool> {
+    let (modified1, modified2) = (fs::metadata(path1)?.modified()?, fs::metadata(path2)?.modified()?);
+    Ok(modified1 > modified2 && !times_match(modified1, modified2, granularity))
+}
+
+/// Checks whether the files at `path1` and `path2` have creation times at least `granularity`
+/// apart. Files whose creation time isn't available are treated as created at the same time.
+fn created_differs(path1: &Path, path2: &Path, granularity: Duration) -> bool {
+    match (std::fs::metadata(path1).and_then(|m| m.created()), std::fs::metadata(path2).and_then(|m| m.created())) {
+        (Ok(created1), Ok(created2)) => !times_match(created1, created2, granularity),
+        _ => false,
+    }
+}
+
+/// Lists the attributes compared with [`CompareOptions::strict_metadata`] which differ between
+/// the files at `path1` and `path2`. Attributes that can't be read are treated as equal.
+fn metadata_differences(path1: &Path, path2: &Path, options: &CompareOptions) -> Vec<MetadataDifference> {
+    let mut differences = vec![];
+    if let (Ok(modified1), Ok(modified2)) = (fs::metadata(path1).and_then(|m| m.modified()), fs::metadata(path2).and_then(|m| m.modified())) {
+        if !times_match(modified1, modified2, options.mtime_granularity.unwrap_or(DEFAULT_MTIME_GRANULARITY)) {
+            differences.push(MetadataDifference::Modified);
+        }
+    }
+    if permissions_differ(path1, path2, options.permission_mask.unwrap_or(0o7777)) {
+        differences.push(MetadataDifference::Permissions);
+    }
+    differences
+}
+
+/// Checks whether the files at `path1` and `path2` have different permissions, i.e. different
+/// mode bits within `mask` on Unix and a different read-only flag elsewhere. Files whose metadata
+/// can't be read are treated as having the same permissions.
+fn permissions_differ(path1: &Path, path2: &Path, mask: u32) -> bool {
+    match (std::fs::metadata(path1), std::fs::metadata(path2)) {
+        (Ok(m1), Ok(m2)) => permission_bits(&m1, mask) != permission_bits(&m2, mask),
+        _ => false,
+    }
+}
+
+#[cfg(unix)]
+fn permission_bits(metadata: &std::fs::Metadata, mask: u32) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+
+    metadata.permissions().mode() & 0o7777 & mask
+}
+
+#[cfg(not(unix))]
+fn permission_bits(metadata: &std::fs::Metadata, _mask: u32) -> u32 {
+    metadata.permissions().readonly() as u32
+}
@@ -0,0 +1,46 @@
+use fxhash::FxHashSet;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::{Error, FileStatus, FolderCompare};
+
+/// Writes `result` to `writer` like `diff -rq` reports it, see [`FolderCompare::write_diff_rq`].
+pub(crate) fn write_diff_rq<W: Write>(result: &FolderCompare, mut writer: W) -> Result<(), Error> {
+    let mut reported: FxHashSet<PathBuf> = FxHashSet::default();
+    for event in result.events() {
+        let (root, other) = match event.status {
+            FileStatus::Deleted => (&result.path2, &result.path1),
+            _ => (&result.path1, &result.path2),
+        };
+        let relative = event.path.strip_prefix(root).unwrap_or(&event.path);
+        match event.status {
+            FileStatus::Changed => {
+                writeln!(writer, "Files {} and {} differ", root.join(relative).display(), other.join(relative).display())?;
+            }
+            FileStatus::New | FileStatus::Deleted => {
+                let missing = topmost_missing(relative, other);
+                if reported.insert(root.join(missing)) {
+                    let parent = match missing.parent() {
+                        Some(parent) if !parent.as_os_str().is_empty() => root.join(parent),
+                        _ => root.to_path_buf(),
+                    };
+                    let name = missing.file_name().unwrap_or(missing.as_os_str());
+                    writeln!(writer, "Only in {}: {}", parent.display(), name.to_string_lossy())?;
+                }
+            }
+            FileStatus::Unchanged => {}
+        }
+    }
+    Ok(())
+}
+
+/// Finds the first ancestor of the `relative` path, or the path itself, missing in the directory
+/// `other`. `diff -rq` reports a directory only found on one side once, instead of every entry
+/// within it.
+fn topmost_missing<'a>(relative: &'a Path, other: &Path) -> &'a Path {
+    relative.ancestors()
+        .filter(|ancestor| !ancestor.as_os_str().is_empty())
+        .take_while(|ancestor| other.join(ancestor).symlink_metadata().is_err())
+        .last()
+        .unwrap_or(relative)
+}
@@ -0,0 +1,103 @@
+use fxhash::FxHasher;
+use regex::RegexSet;
+use std::hash::Hasher;
+use std::io;
+use std::path::Path;
+
+use crate::hash::ContentHasher;
+use crate::walk::entries_to_compare;
+use crate::{CompareOptions, EntryOrder, Error};
+
+/// Hashed before the seed of [`tree_hash_seeded`]. Writing a seed of 0 alone would leave the
+/// initial state of `FxHasher` unchanged and yield the fingerprint of [`tree_hash`].
+const SEED_MARKER: &[u8] = b"folder_compare seed";
+
+/// Computes a single fingerprint of all files in the directory at `path`, leaving out everything
+/// matching the `excluded` patterns. The fingerprint covers the relative path and the content of
+/// every file, so it changes whenever a file is added, removed, renamed or modified.
+///
+/// Files are visited sorted by name, so the fingerprint doesn't depend on the order the file
+/// system returns them in, and only relative paths are hashed, so two identical trees yield the
+/// same fingerprint wherever they are located. Like the comparison it is based on `FxHasher`, see
+/// [`has_changed_since`] for its limits.
+///
+/// # Example
+///
+///```no_run
+/// use std::path::Path;
+/// use folder_compare::tree_hash;
+///
+/// let fingerprint = tree_hash(Path::new("/tmp/a"), &vec![".txt".to_string()]).unwrap();
+///```
+///
+pub fn tree_hash(path: &Path, excluded: &Vec<String>) -> Result<u64, Error> {
+    fingerprint(path, excluded, FxHasher::default())
+}
+
+/// Computes the fingerprint of the directory at `path` like [`tree_hash`] does, but hashes a fixed
+/// marker and `seed` before any file. Different seeds yield different fingerprints for the same
+/// tree, which keeps fingerprints used as cache keys in different contexts apart. Thanks to the
+/// marker the fingerprint also differs from the one of [`tree_hash`], even for a seed of 0, so it
+/// can't be checked with [`has_changed_since`].
+///
+/// # Example
+///
+///```no_run
+/// use std::path::Path;
+/// use folder_compare::tree_hash_seeded;
+///
+/// let fingerprint = tree_hash_seeded(Path::new("/tmp/a"), &vec![], 42).unwrap();
+///```
+///
+pub fn tree_hash_seeded(path: &Path, excluded: &Vec<String>, seed: u64) -> Result<u64, Error> {
+    let mut hasher = FxHasher::default();
+    hasher.write(SEED_MARKER);
+    hasher.write_u64(seed);
+    fingerprint(path, excluded, hasher)
+}
+
+/// Adds the relative path and content hash of every file in the directory at `path` to `hasher`,
+/// visiting them sorted by name, and returns the result. Paths are added as their bytes prefixed
+/// with their length rather than through `impl Hash for Path`, whose output isn't specified and
+/// may change between Rust releases, so recorded fingerprints stay valid.
+fn fingerprint(path: &Path, excluded: &Vec<String>, mut hasher: FxHasher) -> Result<u64, Error> {
+    let set = RegexSet::new(excluded)?;
+    let options = CompareOptions {
+        order: Some(EntryOrder::by_file_name()),
+        ..Default::default()
+    };
+
+    let mut content = ContentHasher::new();
+    for entry in entries_to_compare(path, &set, &options) {
+        let entry = entry.map_err(io::Error::from)?;
+        let relative = entry.path().strip_prefix(path)?.as_os_str().as_encoded_bytes();
+        hasher.write_u64(relative.len() as u64);
+        hasher.write(relative);
+        hasher.write_u64(content.hash_file(entry.path(), &mut |_| {})?);
+    }
+    Ok(hasher.finish())
+}
+
+/// Checks whether the directory at `path` changed since `fingerprint` was recorded with
+/// [`tree_hash`] using the same `excluded` patterns. This is a lot cheaper than a full comparison
+/// if nothing changed, but it can't tell what changed.
+///
+/// `FxHasher` is a fast, non-cryptographic 64 bit hash. Two different trees yielding the same
+/// fingerprint is very unlikely by accident, but can't be ruled out, and nothing prevents
+/// collisions made on purpose. As `FxHasher` depends on the word size, fingerprints are only
+/// comparable between machines with the same pointer width.
+///
+/// # Example
+///
+///```no_run
+/// use std::path::Path;
+/// use folder_compare::has_changed_since;
+///
+/// if has_changed_since(Path::new("/tmp/a"), 0x1234_5678, &vec![]).unwrap() {
+///     println!("/tmp/a changed");
+/// }
+///```
+///
+pub fn has_changed_since(path: &Path, fingerprint: u64, excluded: &Vec<String>) -> Result<bool, Error> {
+    Ok(tree_hash(path, excluded)? != fingerprint)
+}
@@ -0,0 +1,67 @@
+use git2::{ErrorCode, ObjectType, Oid, Repository, Tree, TreeWalkMode, TreeWalkResult};
+use regex::RegexSet;
+use std::path::{Path, PathBuf};
+
+use crate::{Error, FolderCompare};
+
+/// Compares two trees of the git repository at `repo` without checking out either of them, e.g.
+/// two commits in CI. `treeish1` and `treeish2` are anything git resolves to a tree, like commit
+/// hashes, branch names, tags or `HEAD~1`. Every blob of the first tree is looked up at the same
+/// path in the second one and files are classified by the object ids git already stored for them,
+/// so no content is read or hashed at all. Everything whose tree-relative path matches the
+/// `excluded` patterns is left out.
+///
+/// Like [`FolderCompare::from_sources`] the paths stored in the result are relative to the root of
+/// the trees. Blobs only in the first tree are new, blobs whose counterpart has a different id or
+/// file mode or isn't a blob are changed, and blobs only in the second tree aren't listed. The
+/// file mode tells regular files, executable files and symbolic links apart, so making a file
+/// executable or replacing it with a link to the same target changes it. Symbolic links are
+/// compared by their targets, which git stores as blobs, and submodules are left out.
+///
+/// Available with the `git` feature.
+///
+/// # Example
+///
+///```no_run
+/// use std::path::Path;
+/// use folder_compare::compare_git_trees;
+///
+/// let result = compare_git_trees(Path::new("/src/project"), "HEAD", "HEAD~1", &vec![]).unwrap();
+/// println!("{} files changed since the last commit", result.changed_files.len());
+///```
+///
+pub fn compare_git_trees(repo: &Path, treeish1: &str, treeish2: &str, excluded: &Vec<String>) -> Result<FolderCompare, Error> {
+    let set = RegexSet::new(excluded)?;
+    let repo = Repository::open(repo)?;
+    let tree1 = repo.revparse_single(treeish1)?.peel_to_tree()?;
+    let tree2 = repo.revparse_single(treeish2)?.peel_to_tree()?;
+
+    let mut result = FolderCompare::default();
+    for (path, id, mode) in blobs(&tree1)? {
+        if set.matches(&path.to_string_lossy()).matched_any() {
+            continue;
+        }
+
+        match tree2.get_path(&path) {
+            Ok(entry) if entry.kind() == Some(ObjectType::Blob) && entry.id() == id && entry.filemode() == mode => {
+                result.unchanged_files.push(path)
+            }
+            Ok(_) => result.changed_files.push(path),
+            Err(ref error) if error.code() == ErrorCode::NotFound => result.new_files.push(path),
+            Err(error) => return Err(error.into()),
+        }
+    }
+    Ok(result)
+}
+
+/// Lists the paths, object ids and file modes of all blobs within `tree` and its subtrees.
+fn blobs(tree: &Tree) -> Result<Vec<(PathBuf, Oid, i32)>, Error> {
+    let mut blobs = vec![];
+    tree.walk(TreeWalkMode::PreOrder, |dir, entry| {
+        if let (Some(ObjectType::Blob), Some(name)) = (entry.kind(), entry.name()) {
+            blobs.push((Path::new(dir).join(name), entry.id(), entry.filemode()));
+        }
+        TreeWalkResult::Ok
+    })?;
+    Ok(blobs)
+}
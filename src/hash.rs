@@ -0,0 +1,382 @@
+use fxhash::FxHasher;
+use std::fs::File;
+use std::hash::Hasher;
+use regex::bytes::Regex;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::{CompareOptions, Error};
+
+/// Size of the chunks files are read in for hashing, unless limited by
+/// [`CompareOptions::max_memory`]. It has to stay a multiple of 8, so hashing chunk by chunk yields
+/// the same value as hashing the whole content at once.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Size of the blocks read at every offset of [`CompareOptions::sample_offsets`].
+const SAMPLE_SIZE: usize = 4096;
+
+/// Size of the blocks compared by [`differing_fraction`].
+const BLOCK_SIZE: usize = 4096;
+
+/// Size of the chunks files are read in for hashing them on several threads, unless limited by
+/// [`CompareOptions::max_memory`].
+#[cfg(feature = "blake3")]
+const PARALLEL_CHUNK_SIZE: usize = 16 * 1024 * 1024;
+
+/// Number of bytes at the start of a file checked for NUL bytes to tell binary files from text.
+const BINARY_CHECK_SIZE: usize = 8000;
+
+/// NUL bytes hashed in one go by [`ContentHasher::hash_trimmed`].
+const NULLS: [u8; 4096] = [0; 4096];
+
+/// Hashes the file at `path` and checks the result against an `expected` hash, e.g. one recorded
+/// in a manifest of an earlier run.
+///
+/// # Example
+///
+///```no_run
+/// use std::path::Path;
+/// use folder_compare::verify_file;
+///
+/// let matches = verify_file(Path::new("/tmp/a/test.txt"), 0x1234_5678).unwrap();
+///```
+///
+pub fn verify_file(path: &Path, expected: u64) -> Result<bool, Error> {
+    Ok(hash_file(path)? == expected)
+}
+
+/// Hashes the content of the file at `path` with `FxHasher`, the same way the comparison does.
+/// The result can be recorded and checked later on with [`verify_file`].
+pub fn hash_file(path: &Path) -> Result<u64, Error> {
+    ContentHasher::new().hash_file(path, &mut |_| {})
+}
+
+/// Hashes everything readable from `reader`.
+pub(crate) fn hash_reader<R: Read>(reader: R) -> io::Result<u64> {
+    ContentHasher::new().hash_reader(reader, &mut |_| {})
+}
+
+/// Checks whether the file at `path` looks like binary data, reading only its first
+/// `BINARY_CHECK_SIZE` bytes. Files that can't be read are taken as text.
+pub(crate) fn file_is_binary(path: &Path) -> bool {
+    let mut prefix = Vec::with_capacity(BINARY_CHECK_SIZE);
+    match File::open(path).and_then(|file| file.take(BINARY_CHECK_SIZE as u64).read_to_end(&mut prefix)) {
+        Ok(_) => is_binary(&prefix),
+        Err(_) => false,
+    }
+}
+
+/// Compares the files at `path1` and `path2` block by block and returns the share of blocks
+/// differing from the block at the same position in the other file, between 0 and 1. Blocks of the
+/// larger file past the end of the other one count as differing. `on_read` is called with the
+/// number of bytes read for every pair of blocks.
+pub(crate) fn differing_fraction(path1: &Path, path2: &Path, on_read: &mut dyn FnMut(u64)) -> io::Result<f64> {
+    let (mut file1, mut file2) = (File::open(path1)?, File::open(path2)?);
+    let (mut block1, mut block2) = (vec![0; BLOCK_SIZE], vec![0; BLOCK_SIZE]);
+    let (mut blocks, mut differing) = (0, 0);
+    loop {
+        let (read1, read2) = (fill_chunk(&mut file1, &mut block1)?, fill_chunk(&mut file2, &mut block2)?);
+        if read1 == 0 && read2 == 0 {
+            break;
+        }
+        on_read((read1 + read2) as u64);
+        blocks += 1;
+        if block1[..read1] != block2[..read2] {
+            differing += 1;
+        }
+    }
+    Ok(if blocks == 0 { 0.0 } else { differing as f64 / blocks as f64 })
+}
+
+/// Checks whether `content` looks like binary data, which is the case if a NUL byte shows up within
+/// its first `BINARY_CHECK_SIZE` bytes.
+pub(crate) fn is_binary(content: &[u8]) -> bool {
+    content.iter().take(BINARY_CHECK_SIZE).any(|byte| *byte == 0)
+}
+
+/// Hashes file contents, keeping its read buffer between files so repeated comparisons don't
+/// allocate it again.
+#[derive(Debug, Clone)]
+pub(crate) struct ContentHasher {
+    buffer: Vec<u8>,
+    /// Lines of text files matching this pattern are left out of the hash, see
+    /// [`CompareOptions::ignore_line_pattern`].
+    ignored_lines: Option<Regex>,
+}
+
+impl ContentHasher {
+    pub(crate) fn new() -> Self {
+        ContentHasher::with_max_memory(None)
+    }
+
+    /// Sets up a hasher whose read buffer doesn't exceed `max_memory` bytes. The buffer is kept a
+    /// multiple of 8 bytes and at least 8 bytes large, so the hashes stay the same.
+    pub(crate) fn with_max_memory(max_memory: Option<usize>) -> Self {
+        let size = max_memory.map_or(CHUNK_SIZE, |max| (max.min(CHUNK_SIZE) / 8 * 8).max(8));
+        ContentHasher { buffer: vec![0; size], ignored_lines: None }
+    }
+
+    /// Checks whether the content of the file at `prefix` is the start of the content of the file
+    /// at `path`, reading both files up to the end of `prefix` only. Both files are read into the
+    /// halves of the read buffer, so no more memory is needed than for hashing.
+    pub(crate) fn is_prefix_of(&mut self, prefix: &Path, path: &Path) -> io::Result<bool> {
+        let (mut prefix, mut file) = (File::open(prefix)?, File::open(path)?);
+        let half = self.buffer.len() / 2;
+        let (expected, actual) = self.buffer.split_at_mut(half);
+        loop {
+            let read = fill_chunk(&mut prefix, expected)?;
+            if read == 0 {
+                return Ok(true);
+            }
+            if fill_chunk(&mut file, &mut actual[..read])? < read || expected[..read] != actual[..read] {
+                return Ok(false);
+            }
+        }
+    }
+
+    /// Leaves the lines of text files matching `pattern` out of the hashes computed by
+    /// [`ContentHasher::hash_for_compare`].
+    pub(crate) fn ignoring_lines(self, pattern: Option<Regex>) -> Self {
+        ContentHasher { ignored_lines: pattern, ..self }
+    }
+
+    /// Hashes the file at `path`, calling `on_read` with the size of every chunk read.
+    pub(crate) fn hash_file(&mut self, path: &Path, on_read: &mut dyn FnMut(u64)) -> Result<u64, Error> {
+        Ok(self.hash_reader(File::open(path)?, on_read)?)
+    }
+
+    /// Hashes the file at `path` for comparing it with its counterpart, applying the content
+    /// normalizations enabled in `options` to text files.
+    pub(crate) fn hash_for_compare(&mut self, path: &Path, options: &CompareOptions, on_read: &mut dyn FnMut(u64)) -> Result<u64, Error> {
+        if let Some(ref offsets) = options.sample_offsets {
+            return self.hash_samples(path, offsets, on_read);
+        }
+        if let Some(ref pattern) = self.ignored_lines {
+            if !file_is_binary(path) {
+                return Ok(hash_lines(path, pattern, options.ignore_case, self.buffer.len(), on_read)?);
+            }
+        }
+        if options.ignore_trailing_nulls {
+            let (ignore_case, mut binary) = (options.ignore_case, None);
+            return Ok(self.hash_trimmed(File::open(path)?, on_read, |chunk| {
+                if ignore_case && !*binary.get_or_insert_with(|| is_binary(chunk)) {
+                    chunk.make_ascii_lowercase();
+                }
+            })?);
+        }
+        #[cfg(feature = "blake3")]
+        {
+            if let Some(threshold) = options.parallel_hash_threshold {
+                let file = File::open(path)?;
+                if !options.ignore_case && file.metadata()?.len() >= threshold {
+                    return Ok(hash_parallel(file, options.max_memory, on_read)?);
+                }
+            }
+        }
+        #[cfg(feature = "sparse")]
+        {
+            if options.sparse_files && !options.ignore_case {
+                return Ok(self.hash_sparse(path, on_read)?);
+            }
+        }
+        if !options.ignore_case {
+            return self.hash_file(path, on_read);
+        }
+
+        let mut binary = None;
+        Ok(self.hash_chunks(File::open(path)?, on_read, |chunk| {
+            if !*binary.get_or_insert_with(|| is_binary(chunk)) {
+                chunk.make_ascii_lowercase();
+            }
+        })?)
+    }
+
+    /// Hashes the file at `path` like [`ContentHasher::hash_file`] does, but without reading the
+    /// chunks lying in holes of a sparse file. These are hashed as zeros, so the hash is the same as
+    /// when reading them. `on_read` is called for them as well, to keep progress reports complete.
+    #[cfg(feature = "sparse")]
+    fn hash_sparse(&mut self, path: &Path, on_read: &mut dyn FnMut(u64)) -> io::Result<u64> {
+        let mut file = File::open(path)?;
+        let size = file.metadata()?.len();
+        let chunk = self.buffer.len() as u64;
+        let mut hasher = FxHasher::default();
+        let mut region = crate::sparse::data_region(&file, 0)?;
+        let mut offset = 0;
+        loop {
+            if let Some((_, end)) = region {
+                if end <= offset {
+                    region = crate::sparse::data_region(&file, offset)?;
+                }
+            }
+            let read = match region {
+                Some((start, _)) if start < offset + chunk => {
+                    file.seek(SeekFrom::Start(offset))?;
+                    fill_chunk(&mut file, &mut self.buffer)?
+                }
+                _ => {
+                    let hole = chunk.min(size.saturating_sub(offset)) as usize;
+                    self.buffer[..hole].fill(0);
+                    hole
+                }
+            };
+            hasher.write(&self.buffer[..read]);
+            if read > 0 {
+                on_read(read as u64);
+            }
+            if read < self.buffer.len() {
+                return Ok(hasher.finish());
+            }
+            offset += read as u64;
+        }
+    }
+
+    /// Hashes the size of the file at `path` and a block of `SAMPLE_SIZE` bytes at each of the
+    /// `offsets`, given as fractions of the size. Blocks reaching past the end are moved back to
+    /// end with the file.
+    fn hash_samples(&mut self, path: &Path, offsets: &[f64], on_read: &mut dyn FnMut(u64)) -> Result<u64, Error> {
+        let mut file = File::open(path)?;
+        let size = file.metadata()?.len();
+        let block = SAMPLE_SIZE.min(self.buffer.len());
+
+        let mut hasher = FxHasher::default();
+        hasher.write_u64(size);
+        for offset in offsets {
+            let start = ((size as f64 * offset.clamp(0.0, 1.0)) as u64).min(size.saturating_sub(block as u64));
+            file.seek(SeekFrom::Start(start))?;
+            let read = fill_chunk(&mut file, &mut self.buffer[..block])?;
+            hasher.write(&self.buffer[..read]);
+            if read > 0 {
+                on_read(read as u64);
+            }
+        }
+        Ok(hasher.finish())
+    }
+
+    /// Hashes everything readable from `reader`, calling `on_read` with the size of every chunk.
+    pub(crate) fn hash_reader<R: Read>(&mut self, reader: R, on_read: &mut dyn FnMut(u64)) -> io::Result<u64> {
+        self.hash_chunks(reader, on_read, |_| {})
+    }
+
+    /// Hashes everything readable from `reader` in chunks of the size of the buffer. Every chunk is
+    /// passed to `transform` before it is hashed and `on_read` is called with its size.
+    fn hash_chunks<R, F>(&mut self, mut reader: R, on_read: &mut dyn FnMut(u64), mut transform: F) -> io::Result<u64>
+    where
+        R: Read,
+        F: FnMut(&mut [u8]),
+    {
+        let mut hasher = FxHasher::default();
+        let buffer = &mut self.buffer;
+        loop {
+            let read = fill_chunk(&mut reader, buffer)?;
+            transform(&mut buffer[..read]);
+            hasher.write(&buffer[..read]);
+            if read > 0 {
+                on_read(read as u64);
+            }
+            if read < buffer.len() {
+                return Ok(hasher.finish());
+            }
+        }
+    }
+
+    /// Hashes everything readable from `reader` like [`ContentHasher::hash_chunks`] does, but
+    /// leaves out the NUL bytes at the end. A run of NUL bytes at the end of a chunk is only hashed
+    /// once a later chunk turns out to have other content.
+    fn hash_trimmed<R, F>(&mut self, mut reader: R, on_read: &mut dyn FnMut(u64), mut transform: F) -> io::Result<u64>
+    where
+        R: Read,
+        F: FnMut(&mut [u8]),
+    {
+        let mut hasher = FxHasher::default();
+        let buffer = &mut self.buffer;
+        let mut nulls: u64 = 0;
+        loop {
+            let read = fill_chunk(&mut reader, buffer)?;
+            transform(&mut buffer[..read]);
+            match buffer[..read].iter().rposition(|byte| *byte != 0) {
+                Some(last) => {
+                    for _ in 0..nulls / NULLS.len() as u64 {
+                        hasher.write(&NULLS);
+                    }
+                    hasher.write(&NULLS[..(nulls % NULLS.len() as u64) as usize]);
+                    hasher.write(&buffer[..=last]);
+                    nulls = (read - last - 1) as u64;
+                }
+                None => nulls += read as u64,
+            }
+            if read > 0 {
+                on_read(read as u64);
+            }
+            if read < buffer.len() {
+                return Ok(hasher.finish());
+            }
+        }
+    }
+}
+
+/// Hashes the text file at `path` line by line, leaving out the lines matching `pattern`. Line
+/// endings aren't matched, so `$` matches the end of a line. With `ignore_case` ASCII letters are
+/// lowercased after matching. `on_read` is called about every `chunk` bytes read.
+fn hash_lines(path: &Path, pattern: &Regex, ignore_case: bool, chunk: usize, on_read: &mut dyn FnMut(u64)) -> io::Result<u64> {
+    let mut reader = BufReader::with_capacity(chunk, File::open(path)?);
+    let mut hasher = FxHasher::default();
+    let mut line = vec![];
+    let mut unreported = 0;
+    loop {
+        line.clear();
+        let read = reader.read_until(b'\n', &mut line)?;
+        unreported += read;
+        if unreported > 0 && (read == 0 || unreported >= chunk) {
+            on_read(unreported as u64);
+            unreported = 0;
+        }
+        if read == 0 {
+            return Ok(hasher.finish());
+        }
+        let content = line.strip_suffix(b"\n").map_or(&line[..], |l| l.strip_suffix(b"\r").unwrap_or(l));
+        if pattern.is_match(content) {
+            continue;
+        }
+        if ignore_case {
+            line.make_ascii_lowercase();
+        }
+        hasher.write(&line);
+    }
+}
+
+/// Hashes everything readable from `reader` with BLAKE3, hashing every chunk on all cores, see
+/// [`CompareOptions::parallel_hash_threshold`]. The hash is made up of the first 8 bytes of the
+/// BLAKE3 hash. `on_read` is called with the size of every chunk.
+#[cfg(feature = "blake3")]
+fn hash_parallel<R: Read>(mut reader: R, max_memory: Option<usize>, on_read: &mut dyn FnMut(u64)) -> io::Result<u64> {
+    let mut buffer = vec![0; max_memory.map_or(PARALLEL_CHUNK_SIZE, |max| max.clamp(1, PARALLEL_CHUNK_SIZE))];
+    let mut hasher = blake3::Hasher::new();
+    loop {
+        let read = fill_chunk(&mut reader, &mut buffer)?;
+        hasher.update_rayon(&buffer[..read]);
+        if read > 0 {
+            on_read(read as u64);
+        }
+        if read < buffer.len() {
+            let hash = hasher.finalize();
+            let mut prefix = [0; 8];
+            prefix.copy_from_slice(&hash.as_bytes()[..8]);
+            return Ok(u64::from_le_bytes(prefix));
+        }
+    }
+}
+
+/// Reads from `reader` until `buffer` is full or the end is reached and returns the number of
+/// bytes read. Only the last chunk of a file may be shorter than the buffer.
+fn fill_chunk<R: Read>(reader: &mut R, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
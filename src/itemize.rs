@@ -0,0 +1,93 @@
+use fxhash::FxHashSet;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use crate::{times_match, Error, FileStatus, FolderCompare, DEFAULT_MTIME_GRANULARITY};
+
+/// Writes `result` to `writer` in the format of `rsync --itemize-changes`, see
+/// [`FolderCompare::write_itemized`].
+pub(crate) fn write_itemized<W: Write>(result: &FolderCompare, mut writer: W) -> Result<(), Error> {
+    let permissions: FxHashSet<&Path> = result.permissions_changed.iter().map(|path| path.as_path()).collect();
+    let ownership: FxHashSet<&Path> = result.ownership_changed.iter().map(|path| path.as_path()).collect();
+
+    for event in result.events() {
+        let (root, other) = match event.status {
+            FileStatus::Deleted => (&result.path2, &result.path1),
+            _ => (&result.path1, &result.path2),
+        };
+        let relative = event.path.strip_prefix(root).unwrap_or(&event.path);
+        let kind = file_kind(&event.path);
+        let flags = match event.status {
+            FileStatus::New => format!("{}{}+++++++++", if kind == 'f' { '>' } else { 'c' }, kind),
+            FileStatus::Deleted => format!("{:<11}", "*deleting"),
+            status => {
+                let counterpart = other.join(relative);
+                let (size, modified) = sizes_and_times_differ(&event.path, &counterpart);
+                let (owner, group) = if ownership.contains(event.path.as_path()) {
+                    owner_and_group_differ(&event.path, &counterpart)
+                } else {
+                    (false, false)
+                };
+                let changed = status == FileStatus::Changed;
+                let attributes: String = [
+                    (changed, 'c'),
+                    (size, 's'),
+                    (modified, 't'),
+                    (permissions.contains(event.path.as_path()), 'p'),
+                    (owner, 'o'),
+                    (group, 'g'),
+                ].iter().map(|&(set, flag)| if set { flag } else { '.' }).collect();
+                if !attributes.contains(|flag| flag != '.') {
+                    continue;
+                }
+                format!("{}{}{}...", if changed { '>' } else { '.' }, kind, attributes)
+            }
+        };
+        let suffix = if kind == 'd' { "/" } else { "" };
+        writeln!(writer, "{} {}{}", flags, relative.to_string_lossy(), suffix)?;
+    }
+    Ok(())
+}
+
+/// The letter rsync uses for the kind of entry at `path`: `d` for directories, `L` for symbolic
+/// links and `f` for files and everything that can't be read.
+fn file_kind(path: &Path) -> char {
+    match fs::symlink_metadata(path).map(|m| m.file_type()) {
+        Ok(kind) if kind.is_dir() => 'd',
+        Ok(kind) if kind.is_symlink() => 'L',
+        _ => 'f',
+    }
+}
+
+/// Checks whether the files at `path1` and `path2` differ in size and in modification time, with
+/// modification times less than the default granularity apart treated as equal. Files whose
+/// metadata can't be read are treated as not differing.
+fn sizes_and_times_differ(path1: &Path, path2: &Path) -> (bool, bool) {
+    match (fs::metadata(path1), fs::metadata(path2)) {
+        (Ok(m1), Ok(m2)) => {
+            let modified = match (m1.modified(), m2.modified()) {
+                (Ok(modified1), Ok(modified2)) => !times_match(modified1, modified2, DEFAULT_MTIME_GRANULARITY),
+                _ => false,
+            };
+            (m1.len() != m2.len(), modified)
+        }
+        _ => (false, false),
+    }
+}
+
+/// Checks whether the files at `path1` and `path2` differ in owner and in group.
+#[cfg(unix)]
+fn owner_and_group_differ(path1: &Path, path2: &Path) -> (bool, bool) {
+    use std::os::unix::fs::MetadataExt;
+
+    match (fs::metadata(path1), fs::metadata(path2)) {
+        (Ok(m1), Ok(m2)) => (m1.uid() != m2.uid(), m1.gid() != m2.gid()),
+        _ => (false, false),
+    }
+}
+
+#[cfg(not(unix))]
+fn owner_and_group_differ(_path1: &Path, _path2: &Path) -> (bool, bool) {
+    (false, false)
+}
@@ -0,0 +1,104 @@
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::{Comparator, CompareOptions, Error, FileStatus, ResultSink};
+
+/// Compares `path1` with `path2` like [`FolderCompare::new`] does and writes every entry to
+/// `writer` as a JSON object on its own line, as soon as the entry is classified. Entries aren't
+/// collected into a result, so memory use doesn't grow with the size of the trees:
+///
+///```text
+/// {"status":"changed","path":"/tmp/a/test.xls"}
+/// {"status":"new","path":"/tmp/a/test.abc"}
+///```
+///
+/// The status is one of `new`, `changed`, `unchanged` and `deleted`. Paths are written the same
+/// way they are stored in [`FolderCompare`]. JSON strings can only hold Unicode, so paths that
+/// aren't valid UTF-8 are encoded lossily, replacing invalid sequences with U+FFFD.
+///
+/// # Example
+///
+///```no_run
+/// use std::io;
+/// use std::path::Path;
+/// use folder_compare::write_jsonl;
+///
+/// write_jsonl(Path::new("/tmp/a"), Path::new("/tmp/b"), &vec![], io::stdout()).unwrap();
+///```
+///
+/// [`FolderCompare::new`]: crate::FolderCompare::new
+/// [`FolderCompare`]: crate::FolderCompare
+pub fn write_jsonl<W: Write>(path1: &Path, path2: &Path, excluded: &Vec<String>, writer: W) -> Result<(), Error> {
+    let mut sink = JsonlSink { writer, error: None };
+    Comparator::new(excluded, CompareOptions::default())?.compare_into(path1, path2, &mut sink)?;
+    match sink.error {
+        Some(error) => Err(error.into()),
+        None => Ok(sink.writer.flush()?),
+    }
+}
+
+/// A [`ResultSink`] writing every entry to `writer` as a JSON line. Once writing failed, all
+/// further entries are dropped and the error is kept for reporting it after the comparison.
+struct JsonlSink<W> {
+    writer: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> JsonlSink<W> {
+    fn write(&mut self, status: FileStatus, path: &Path) {
+        if self.error.is_none() {
+            let line = format!("{{\"status\":\"{}\",\"path\":{}}}", status_name(status), json_string(&path.to_string_lossy()));
+            self.error = writeln!(self.writer, "{}", line).err();
+        }
+    }
+}
+
+impl<W: Write> ResultSink for JsonlSink<W> {
+    fn on_new(&mut self, path: &Path) {
+        self.write(FileStatus::New, path);
+    }
+
+    fn on_changed(&mut self, path: &Path) {
+        self.write(FileStatus::Changed, path);
+    }
+
+    fn on_unchanged(&mut self, path: &Path) {
+        self.write(FileStatus::Unchanged, path);
+    }
+
+    fn on_deleted(&mut self, path: &Path) {
+        self.write(FileStatus::Deleted, path);
+    }
+}
+
+/// The name of `status` as written to the JSON lines.
+fn status_name(status: FileStatus) -> &'static str {
+    match status {
+        FileStatus::New => "new",
+        FileStatus::Changed => "changed",
+        FileStatus::Unchanged => "unchanged",
+        FileStatus::Deleted => "deleted",
+    }
+}
+
+/// Quotes `value` as a JSON string, escaping quotes, backslashes and control characters.
+fn json_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
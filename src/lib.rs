@@ -9,18 +9,241 @@ For recognizing changed files, hashing with [`FxHasher`] is used.
 
 [`FxHasher`]: https://github.com/cbreeden/fxhash
 */
+use std::collections::HashMap;
+use std::fs;
+use std::io;
 use std::path::{Path, PathBuf};
-use walkdir::WalkDir;
+use std::time::{Duration, SystemTime};
 use regex::RegexSet;
-use std::hash::Hasher;
-use std::fs::File;
-use fxhash::FxHasher;
-use std::io::Read;
+use std::hash::Hash;
+use fxhash::FxHashSet;
 
+use crate::hash::hash_reader;
+
+#[cfg(feature = "archive")]
+mod archive;
+#[cfg(feature = "cdc")]
+mod cdc;
+mod comparator;
+mod diff_rq;
+mod fingerprint;
+#[cfg(feature = "git")]
+mod git;
+mod hash;
+mod itemize;
+mod jsonl;
+mod manifest;
+mod options;
+#[cfg(feature = "ssh")]
+mod sftp;
+mod sink;
+mod source;
+#[cfg(feature = "sparse")]
+mod sparse;
+mod tree;
+mod walk;
+#[cfg(feature = "notify")]
+mod watch;
+#[cfg(feature = "xattr-cache")]
+mod xattr_cache;
+
+#[cfg(feature = "archive")]
+pub use crate::archive::ZipSource;
+#[cfg(feature = "cdc")]
+pub use crate::cdc::{file_chunks, shared_chunk_bytes, Chunk};
+pub use crate::comparator::{files_equal, identical_files, Comparator};
+pub use crate::fingerprint::{has_changed_since, tree_hash, tree_hash_seeded};
+#[cfg(feature = "git")]
+pub use crate::git::compare_git_trees;
+pub use crate::hash::{hash_file, verify_file};
+pub use crate::jsonl::write_jsonl;
+pub use crate::manifest::{Manifest, ManifestEntry};
+pub use crate::options::{CompareMode, CompareOptions, ConfigureWalk, EntryKinds, EntryOrder, OrderBy, Reclassify, Symlinks, WalkErrors};
+#[cfg(feature = "ssh")]
+pub use crate::sftp::compare_with_sftp;
+pub use crate::sink::ResultSink;
+pub use crate::source::{DirSource, FileMetadata, FileSource};
+pub use crate::tree::{DiffTree, DirStats};
+#[cfg(feature = "notify")]
+pub use crate::watch::FolderWatch;
+
+#[derive(Debug, Default, Clone)]
 pub struct FolderCompare {
+    /// The first directory of the comparison, the one the paths of new, changed and unchanged
+    /// files start with. Empty for comparisons of [`FileSource`]s.
+    pub path1: PathBuf,
+    /// The second directory of the comparison, the one the paths of deleted files start with.
+    /// Empty for comparisons of [`FileSource`]s.
+    pub path2: PathBuf,
     pub changed_files: Vec<PathBuf>,
     pub new_files: Vec<PathBuf>,
     pub unchanged_files: Vec<PathBuf>,
+    /// Files only existing in the second directory, filled when [`CompareOptions::detect_deleted`]
+    /// is set.
+    pub deleted_files: Vec<PathBuf>,
+    /// Special files like FIFOs, sockets or device nodes found in either directory, filled when
+    /// [`CompareOptions::strict_regular`] is set.
+    pub unexpected_entries: Vec<PathBuf>,
+    /// Files existing in both directories whose owner or group differs, regardless of their
+    /// content. Filled when [`CompareOptions::compare_ownership`] is set.
+    pub ownership_changed: Vec<PathBuf>,
+    /// Files existing in both directories whose permissions differ, regardless of their content.
+    /// Filled when [`CompareOptions::compare_permissions`] is set.
+    pub permissions_changed: Vec<PathBuf>,
+    /// Unchanged files whose creation time differs between both directories. Filled when
+    /// [`CompareOptions::compare_created`] is set.
+    pub created_changed: Vec<PathBuf>,
+    /// Files with identical content that count as changed because their metadata differs, along
+    /// with every attribute that differs. Filled when [`CompareOptions::strict_metadata`] is set.
+    pub metadata_differences: Vec<(PathBuf, MetadataDifference)>,
+    /// Files existing in both directories which are hard linked with a different set of files in
+    /// each directory. Filled when [`CompareOptions::compare_hardlinks`] is set.
+    pub hardlink_structure_changed: Vec<PathBuf>,
+    /// Directories existing in both directories whose files have a different set of contents,
+    /// regardless of their names. Filled when [`CompareOptions::compare_content_sets`] is set.
+    pub content_set_changed: Vec<PathBuf>,
+    /// Pairs of a new file and a file only existing in the second directory, whose paths only
+    /// differ in case or Unicode normalization while their content is identical. Filled when
+    /// [`CompareOptions::detect_name_only_differences`] is set. The files are still listed as new
+    /// and, with [`CompareOptions::detect_deleted`], as deleted.
+    pub name_only_differences: Vec<(PathBuf, PathBuf)>,
+    /// Pairs of a file in the first directory and a file in the second directory with identical
+    /// content but different relative paths. Filled when [`CompareOptions::detect_same_content`]
+    /// is set, independent of the classification of the files.
+    pub same_content_different_path: Vec<(PathBuf, PathBuf)>,
+    /// The hashes of the changed files as `(path, hash in first directory, hash in second directory)`,
+    /// as computed for the comparison. Files told apart by their size aren't hashed and not listed,
+    /// see [`CompareMode::Content`].
+    pub changed_hashes: Vec<(PathBuf, u64, u64)>,
+    /// New, changed and deleted files that look like binary data, filled when
+    /// [`CompareOptions::detect_binary`] is set. All other files listed are text.
+    pub binary_files: Vec<PathBuf>,
+    /// Files whose counterpart is a truncated copy, along with the number of bytes missing from
+    /// it, filled when [`CompareOptions::detect_partial`] is set. These files aren't listed as
+    /// changed.
+    pub partial: Vec<(PathBuf, u64)>,
+    /// Set if the comparison stopped before looking at all files, e.g. after reaching
+    /// [`CompareOptions::limit`] or [`CompareOptions::max_bytes_read`]. The lists then only cover
+    /// the files looked at.
+    pub truncated: bool,
+    /// Symbolic links in either directory whose target doesn't exist. Only filled when following
+    /// symbolic links, see [`CompareOptions::symlinks`].
+    pub broken_symlinks: Vec<PathBuf>,
+    /// Symbolic links in either directory that weren't followed and therefore left out of all
+    /// other lists. Only filled with [`CompareOptions::report_skipped_symlinks`].
+    pub skipped_symlinks: Vec<PathBuf>,
+    /// Files in either directory that took longer than [`CompareOptions::per_file_timeout`] to
+    /// hash. They're left out of all other lists.
+    pub timed_out: Vec<PathBuf>,
+    /// The number of files in either directory left out because they matched an excluded
+    /// pattern, e.g. to check the patterns match what they should. Files within directories
+    /// pruned with [`CompareOptions::prune_dir_names`] aren't seen and therefore not counted. A
+    /// file excluded in both directories is counted twice if the second one is walked as well,
+    /// see [`CompareOptions::detect_deleted`].
+    pub excluded_files_count: usize,
+    /// The number of directories in either directory left out with
+    /// [`CompareOptions::prune_dir_names`].
+    pub pruned_dirs_count: usize,
+    /// The number of symbolic links in either directory left out because they weren't followed,
+    /// see [`CompareOptions::symlinks`]. These are the links listed in
+    /// [`FolderCompare::skipped_symlinks`] with [`CompareOptions::report_skipped_symlinks`].
+    pub symlinks_skipped: usize,
+    /// Every classified entry along with its status, size and modification time, in the order they
+    /// were classified. Filled when [`CompareOptions::collect_metadata`] is set.
+    pub entries: Vec<FileEntry>,
+    /// Files that couldn't be read, filled when [`CompareOptions::continue_on_error`] is set, and
+    /// entries that couldn't be read while walking, see [`CompareOptions::walk_errors`].
+    pub errors: Vec<FileError>,
+}
+
+/// A file that couldn't be read during a comparison, see [`FolderCompare::errors`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FileError {
+    /// The path of the file that couldn't be read.
+    pub path: PathBuf,
+    /// The kind of the error that occurred.
+    pub kind: io::ErrorKind,
+    /// The message of the error that occurred.
+    pub message: String,
+}
+
+impl FileError {
+    pub(crate) fn new(path: &Path, error: &io::Error) -> Self {
+        FileError {
+            path: path.to_path_buf(),
+            kind: error.kind(),
+            message: error.to_string(),
+        }
+    }
+}
+
+/// A classified entry along with its metadata, see [`FolderCompare::entries`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FileEntry {
+    /// The path of the entry, as stored in the other lists of the result.
+    pub path: PathBuf,
+    /// The classification of the entry.
+    pub status: FileStatus,
+    /// The size of the entry in bytes, zero if its metadata couldn't be read.
+    pub size: u64,
+    /// The modification time of the entry, if available.
+    pub modified: Option<SystemTime>,
+}
+
+/// A classified entry of a comparison, see [`FolderCompare::events`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ComparisonEvent {
+    /// The path of the entry, as stored in the list matching its status.
+    pub path: PathBuf,
+    /// The classification of the entry.
+    pub status: FileStatus,
+}
+
+/// The classification of a single entry of a comparison.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FileStatus {
+    /// The entry only exists in the first directory.
+    New,
+    /// The entry exists in both directories with different content.
+    Changed,
+    /// The entry exists in both directories with the same content.
+    Unchanged,
+    /// The entry only exists in the second directory.
+    Deleted,
+}
+
+/// An attribute of a file that differs between both directories, see
+/// [`FolderCompare::metadata_differences`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MetadataDifference {
+    /// The modification times are at least [`CompareOptions::mtime_granularity`] apart.
+    Modified,
+    /// The permissions differ, as compared with [`CompareOptions::compare_permissions`].
+    Permissions,
+}
+
+/// A single step of turning the second directory into a copy of the first one, see
+/// [`FolderCompare::as_operations`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyncOp {
+    /// Copy the new file at this path in the first directory over, creating missing parent
+    /// directories.
+    Create(PathBuf),
+    /// Copy the changed file at this path in the first directory over its counterpart.
+    Overwrite(PathBuf),
+    /// Remove the file at this path in the second directory.
+    Delete(PathBuf),
+}
+
+/// A [`SyncOp`] along with the files it reads and writes, see [`FolderCompare::plan_sync`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedOp {
+    /// The operation, as listed by [`FolderCompare::as_operations`].
+    pub op: SyncOp,
+    /// The file copied over, `None` for deletes.
+    pub source: Option<PathBuf>,
+    /// The file created, overwritten or removed.
+    pub destination: PathBuf,
 }
 
 impl FolderCompare {
@@ -33,7 +256,7 @@ impl FolderCompare {
     ///
     /// The following code recursively iterates over two directories and returns lists of changed and new files
     ///
-    ///```
+    ///```no_run
     /// use std::path::Path;
     /// use folder_compare;
     /// use folder_compare::FolderCompare;
@@ -49,70 +272,595 @@ impl FolderCompare {
     ///```
     ///
     pub fn new(path1: &Path, path2: &Path, excluded: &Vec<String>) -> Result<Self, Error> {
+        let mut result = FolderCompare::default();
+        FolderCompare::compare_into(path1, path2, excluded, &mut result)?;
+        Ok(result)
+    }
 
-        let mut final_object = FolderCompare {
-            changed_files: vec![],
-            new_files: vec![],
-            unchanged_files: vec![]
-        };
+    /// Does the same comparison as [`FolderCompare::new`], but fills `out` instead of a new
+    /// result. All lists of `out` are cleared first and keep their capacity, so comparing again
+    /// and again, e.g. in a watch loop, doesn't allocate them anew every time. `out` is left empty
+    /// if the comparison fails.
+    ///
+    /// # Example
+    ///
+    ///```no_run
+    /// use std::path::Path;
+    /// use folder_compare::FolderCompare;
+    ///
+    /// let mut result = FolderCompare::default();
+    /// for _ in 0..3 {
+    ///     FolderCompare::compare_into(Path::new("/tmp/a"), Path::new("/tmp/b"), &vec![], &mut result).unwrap();
+    /// }
+    ///```
+    ///
+    pub fn compare_into(path1: &Path, path2: &Path, excluded: &Vec<String>, out: &mut FolderCompare) -> Result<(), Error> {
+        Comparator::new(excluded, CompareOptions::default())?.compare_reusing(path1, path2, out)
+    }
 
-        let mut walker = WalkDir::new(path1).into_iter();
-        let set = RegexSet::new(excluded)?;
+    /// Does the same comparison as [`FolderCompare::new`], but only reports the files existing in
+    /// both directories, as changed or unchanged, e.g. to check whether a sync left any content
+    /// drift behind regardless of added or removed files. This is a shorthand for
+    /// [`CompareOptions::changes_only`], so [`FolderCompare::new_files`] and
+    /// [`FolderCompare::deleted_files`] stay empty.
+    ///
+    /// # Example
+    ///
+    ///```no_run
+    /// use std::path::Path;
+    /// use folder_compare::FolderCompare;
+    ///
+    /// let result = FolderCompare::compare_common(Path::new("/tmp/a"), Path::new("/tmp/b"), &vec![]).unwrap();
+    /// assert!(result.new_files.is_empty());
+    ///```
+    ///
+    pub fn compare_common(path1: &Path, path2: &Path, excluded: &Vec<String>) -> Result<Self, Error> {
+        FolderCompare::with_options(path1, path2, excluded, &CompareOptions { changes_only: true, ..Default::default() })
+    }
 
-        loop {
-            let entry = match walker.next() {
-                None => break,
-                Some(Err(_)) => continue,
-                Some(Ok(entry)) => entry,
-            };
-            if !entry.file_type().is_file() {
-                continue;
+    /// Does the same comparison as [`FolderCompare::new`], but allows tuning it with
+    /// [`CompareOptions`].
+    ///
+    /// Like all comparisons of directories it fails with [`Error::NotADirectory`] if `path1` or
+    /// `path2` doesn't exist or isn't a directory.
+    ///
+    /// # Example
+    ///
+    /// The following code also compares the content of directories linked into the first directory
+    ///
+    ///```no_run
+    /// use std::path::Path;
+    /// use folder_compare::{CompareOptions, FolderCompare, Symlinks};
+    ///
+    /// let options = CompareOptions {
+    ///     symlinks: Symlinks::FollowDirectories,
+    ///     ..Default::default()
+    /// };
+    ///
+    /// let result = FolderCompare::with_options(Path::new("/tmp/a"), Path::new("/tmp/b"), &vec![], &options).unwrap();
+    ///```
+    ///
+    pub fn with_options(path1: &Path, path2: &Path, excluded: &Vec<String>, options: &CompareOptions) -> Result<Self, Error> {
+        Comparator::new(excluded, options.clone())?.compare(path1, path2)
+    }
+
+    /// Does the same comparison as [`FolderCompare::with_options`] and reports its progress in bytes.
+    ///
+    /// `progress` is called with the number of bytes read so far and the total number of bytes
+    /// expected to be read. The total is estimated by a walk over both directories summing up the
+    /// sizes of all files to be compared before the actual comparison starts.
+    ///
+    /// Progress is reported after every chunk read, so it also advances smoothly within large files.
+    ///
+    /// # Example
+    ///
+    ///```no_run
+    /// use std::path::Path;
+    /// use folder_compare::{CompareOptions, FolderCompare};
+    ///
+    /// let result = FolderCompare::compare_with_progress_bytes(Path::new("/tmp/a"), Path::new("/tmp/b"), &vec![], &CompareOptions::default(), |read, total| {
+    ///     println!("{} of {} bytes", read, total);
+    /// }).unwrap();
+    ///```
+    ///
+    pub fn compare_with_progress_bytes<F>(path1: &Path, path2: &Path, excluded: &Vec<String>, options: &CompareOptions, progress: F) -> Result<Self, Error>
+    where
+        F: FnMut(u64, u64),
+    {
+        Comparator::new(excluded, options.clone())?.compare_with_progress_bytes(path1, path2, progress)
+    }
+
+    /// Does the same comparison as [`FolderCompare::with_options`] and reports its progress in
+    /// entries, e.g. for a progress bar of trees with lots of small files.
+    ///
+    /// `progress` is called with the number of entries classified so far and the total number of
+    /// entries expected. The total is counted by a walk over the first directory, and the second
+    /// one with [`CompareOptions::detect_deleted`], before the actual comparison starts. It leaves
+    /// out the same entries the comparison does, but doubles the walking. Entries ending up in none
+    /// of the new, changed, unchanged and deleted lists, like
+    /// [`FolderCompare::unexpected_entries`], aren't counted, so the count may end below the total.
+    /// Files that couldn't be read are counted, but entries the walks couldn't read, recorded with
+    /// [`WalkErrors::Record`], are neither counted nor part of the total.
+    ///
+    /// # Example
+    ///
+    ///```no_run
+    /// use std::path::Path;
+    /// use folder_compare::{CompareOptions, FolderCompare};
+    ///
+    /// let result = FolderCompare::compare_with_progress_files(Path::new("/tmp/a"), Path::new("/tmp/b"), &vec![], &CompareOptions::default(), |done, total| {
+    ///     println!("{} of {} files", done, total);
+    /// }).unwrap();
+    ///```
+    ///
+    pub fn compare_with_progress_files<F>(path1: &Path, path2: &Path, excluded: &Vec<String>, options: &CompareOptions, progress: F) -> Result<Self, Error>
+    where
+        F: FnMut(u64, u64),
+    {
+        Comparator::new(excluded, options.clone())?.compare_with_progress_files(path1, path2, progress)
+    }
+
+    /// Does the same comparison as [`FolderCompare::compare_with_progress_files`], but reports its
+    /// progress as the fraction of entries classified, from `0.0` to `1.0`, e.g. for a percentage
+    /// bar. The fraction never decreases. It is `1.0` right away if there is nothing to compare,
+    /// and may end below `1.0` for the same reasons the count may end below the total.
+    ///
+    /// Counting the entries up front costs an extra walk. Callers who don't need a total can
+    /// count the entries with a [`ResultSink`] passed to [`Comparator::compare_into`] instead.
+    ///
+    /// # Example
+    ///
+    ///```no_run
+    /// use std::path::Path;
+    /// use folder_compare::{CompareOptions, FolderCompare};
+    ///
+    /// let result = FolderCompare::compare_with_progress_fraction(Path::new("/tmp/a"), Path::new("/tmp/b"), &vec![], &CompareOptions::default(), |fraction| {
+    ///     println!("{:.0} %", fraction * 100.0);
+    /// }).unwrap();
+    ///```
+    ///
+    pub fn compare_with_progress_fraction<F>(path1: &Path, path2: &Path, excluded: &Vec<String>, options: &CompareOptions, progress: F) -> Result<Self, Error>
+    where
+        F: FnMut(f64),
+    {
+        Comparator::new(excluded, options.clone())?.compare_with_progress_fraction(path1, path2, progress)
+    }
+
+    /// Merges the result of another comparison into this one, e.g. the results of comparing
+    /// several subdirectories one by one.
+    ///
+    /// The entries of `other` are appended to the corresponding lists, so the order within each
+    /// list is kept. Entries already known are skipped, so overlapping comparisons don't list a
+    /// path twice. A path classified differently by both comparisons keeps the classification of
+    /// `self`.
+    ///
+    /// The directories [`FolderCompare::path1`] and [`FolderCompare::path2`] of `self` are kept,
+    /// unless they are empty.
+    pub fn merge(&mut self, other: FolderCompare) {
+        if self.path1.as_os_str().is_empty() && self.path2.as_os_str().is_empty() {
+            self.path1 = other.path1;
+            self.path2 = other.path2;
+        }
+        let mut classified: FxHashSet<PathBuf> = self.changed_files.iter()
+            .chain(self.new_files.iter())
+            .chain(self.unchanged_files.iter())
+            .cloned()
+            .collect();
+        for (target, entries) in [
+            (&mut self.changed_files, other.changed_files),
+            (&mut self.new_files, other.new_files),
+            (&mut self.unchanged_files, other.unchanged_files),
+        ] {
+            target.extend(entries.into_iter().filter(|path| classified.insert(path.clone())));
+        }
+        extend_unique(&mut self.deleted_files, other.deleted_files);
+        extend_unique(&mut self.unexpected_entries, other.unexpected_entries);
+        extend_unique(&mut self.ownership_changed, other.ownership_changed);
+        extend_unique(&mut self.permissions_changed, other.permissions_changed);
+        extend_unique(&mut self.created_changed, other.created_changed);
+        extend_unique(&mut self.metadata_differences, other.metadata_differences);
+        extend_unique(&mut self.hardlink_structure_changed, other.hardlink_structure_changed);
+        extend_unique(&mut self.content_set_changed, other.content_set_changed);
+        extend_unique(&mut self.name_only_differences, other.name_only_differences);
+        extend_unique(&mut self.same_content_different_path, other.same_content_different_path);
+        extend_unique(&mut self.changed_hashes, other.changed_hashes);
+        extend_unique(&mut self.binary_files, other.binary_files);
+        extend_unique(&mut self.partial, other.partial);
+        self.truncated |= other.truncated;
+        extend_unique(&mut self.broken_symlinks, other.broken_symlinks);
+        extend_unique(&mut self.skipped_symlinks, other.skipped_symlinks);
+        extend_unique(&mut self.timed_out, other.timed_out);
+        self.excluded_files_count += other.excluded_files_count;
+        self.pruned_dirs_count += other.pruned_dirs_count;
+        self.symlinks_skipped += other.symlinks_skipped;
+        extend_unique(&mut self.entries, other.entries);
+        extend_unique(&mut self.errors, other.errors);
+    }
+
+    /// Empties all lists, keeping their capacity, and resets the flags.
+    fn clear(&mut self) {
+        self.path1.clear();
+        self.path2.clear();
+        self.changed_files.clear();
+        self.new_files.clear();
+        self.unchanged_files.clear();
+        self.deleted_files.clear();
+        self.unexpected_entries.clear();
+        self.ownership_changed.clear();
+        self.permissions_changed.clear();
+        self.created_changed.clear();
+        self.metadata_differences.clear();
+        self.hardlink_structure_changed.clear();
+        self.content_set_changed.clear();
+        self.name_only_differences.clear();
+        self.same_content_different_path.clear();
+        self.changed_hashes.clear();
+        self.binary_files.clear();
+        self.partial.clear();
+        self.truncated = false;
+        self.broken_symlinks.clear();
+        self.skipped_symlinks.clear();
+        self.timed_out.clear();
+        self.excluded_files_count = 0;
+        self.pruned_dirs_count = 0;
+        self.symlinks_skipped = 0;
+        self.entries.clear();
+        self.errors.clear();
+    }
+
+    /// Lists the changed files along with their size in the first directory, largest first, e.g.
+    /// to look at the biggest differences first. Files of the same size keep the order they were
+    /// found in. Sizes are read when calling this, files that can't be read anymore are listed
+    /// last with a size of zero.
+    pub fn changed_by_size(&self) -> Vec<(PathBuf, u64)> {
+        let mut sized: Vec<(PathBuf, u64)> = self.changed_files.iter()
+            .map(|path| (path.clone(), path.metadata().map(|m| m.len()).unwrap_or(0)))
+            .collect();
+        sized.sort_by_key(|(_, size)| std::cmp::Reverse(*size));
+        sized
+    }
+
+    /// Groups the changed files by their parent directory, e.g. to see where most changes are.
+    /// Within each group the files keep the order they were found in.
+    pub fn changed_by_dir(&self) -> HashMap<PathBuf, Vec<PathBuf>> {
+        group_by_parent(&self.changed_files)
+    }
+
+    /// Groups the new files by their parent directory, see [`FolderCompare::changed_by_dir`].
+    pub fn new_by_dir(&self) -> HashMap<PathBuf, Vec<PathBuf>> {
+        group_by_parent(&self.new_files)
+    }
+
+    /// Groups the deleted files by their parent directory, see [`FolderCompare::changed_by_dir`].
+    pub fn deleted_by_dir(&self) -> HashMap<PathBuf, Vec<PathBuf>> {
+        group_by_parent(&self.deleted_files)
+    }
+
+    /// Maps the relative path of every classified entry to its status, for looking up what
+    /// happened to a specific file without scanning the lists. The paths are relative to
+    /// [`FolderCompare::path1`] for new, changed and unchanged entries and to
+    /// [`FolderCompare::path2`] for deleted ones. A path both new and deleted, e.g. after merging
+    /// overlapping comparisons, keeps the status it has in the first directory.
+    ///
+    /// # Example
+    ///
+    ///```no_run
+    /// use std::path::Path;
+    /// use folder_compare::{FileStatus, FolderCompare};
+    ///
+    /// let result = FolderCompare::new(Path::new("/tmp/a"), Path::new("/tmp/b"), &vec![]).unwrap();
+    ///
+    /// if result.statuses().get(Path::new("src/main.rs")) == Some(&FileStatus::Changed) {
+    ///     println!("main.rs changed");
+    /// }
+    ///```
+    ///
+    pub fn statuses(&self) -> HashMap<PathBuf, FileStatus> {
+        let mut statuses = HashMap::new();
+        for (root, paths, status) in [
+            (&self.path2, &self.deleted_files, FileStatus::Deleted),
+            (&self.path1, &self.unchanged_files, FileStatus::Unchanged),
+            (&self.path1, &self.changed_files, FileStatus::Changed),
+            (&self.path1, &self.new_files, FileStatus::New),
+        ] {
+            for path in paths {
+                let relative = path.strip_prefix(root).unwrap_or(path);
+                statuses.insert(relative.to_path_buf(), status);
             }
+        }
+        statuses
+    }
 
-            if entry.path_is_symlink() {
-                continue;
+    /// Combines the new, changed, unchanged and deleted entries into a single list sorted by their
+    /// paths relative to [`FolderCompare::path1`] and [`FolderCompare::path2`], e.g. for writing a
+    /// changelog. Entries with the same relative path, like a path both new and deleted after
+    /// merging overlapping comparisons, are ordered new, changed, unchanged, deleted.
+    pub fn events(&self) -> Vec<ComparisonEvent> {
+        let mut events: Vec<(&Path, ComparisonEvent)> = vec![];
+        for (root, paths, status) in [
+            (&self.path1, &self.new_files, FileStatus::New),
+            (&self.path1, &self.changed_files, FileStatus::Changed),
+            (&self.path1, &self.unchanged_files, FileStatus::Unchanged),
+            (&self.path2, &self.deleted_files, FileStatus::Deleted),
+        ] {
+            for path in paths {
+                let relative = path.strip_prefix(root).unwrap_or(path);
+                events.push((relative, ComparisonEvent { path: path.clone(), status }));
             }
+        }
+        events.sort_by(|a, b| a.0.cmp(b.0));
+        events.into_iter().map(|(_, event)| event).collect()
+    }
 
-            if set.matches(entry.path().to_str().unwrap()).matched_any() {
-                continue;
+    /// Counts the new, changed, unchanged and deleted entries within every directory, including
+    /// all of its subdirectories, e.g. to see where changes cluster. Directories are keyed by the
+    /// same relative paths as in [`FolderCompare::statuses`], the root by an empty path. Only
+    /// directories containing classified entries are listed.
+    pub fn dir_stats(&self) -> HashMap<PathBuf, DirStats> {
+        DirStats::from_statuses(&self.statuses())
+    }
+
+    /// Shapes the result as a [`DiffTree`] of directories and their entries, each carrying a
+    /// status, e.g. for rendering an expandable tree. The tree is built from
+    /// [`FolderCompare::statuses`], so it uses the same relative paths.
+    pub fn diff_tree(&self) -> DiffTree {
+        DiffTree::from_statuses(self.statuses())
+    }
+
+    /// Lists the operations needed to turn the second directory into a copy of the first one.
+    ///
+    /// All creates come first, followed by all overwrites and then all deletes, each in the order
+    /// the files were found. Applying them in this order never removes a file before its
+    /// replacement is in place. Deletes are only part of the list if the comparison was done with
+    /// [`CompareOptions::detect_deleted`].
+    pub fn as_operations(&self) -> Vec<SyncOp> {
+        self.new_files.iter().cloned().map(SyncOp::Create)
+            .chain(self.changed_files.iter().cloned().map(SyncOp::Overwrite))
+            .chain(self.deleted_files.iter().cloned().map(SyncOp::Delete))
+            .collect()
+    }
+
+    /// Lists the directories within [`FolderCompare::path2`] that only contain deleted entries,
+    /// and directories becoming empty in turn, so they would be left empty once the deletions are
+    /// applied, e.g. for pruning them after syncing. Directories are listed deepest first, so they
+    /// can be removed in this order. The directories are read again to check for entries not
+    /// taking part in the comparison, like excluded files, which keep a directory from becoming
+    /// empty. Only filled if the comparison was done with [`CompareOptions::detect_deleted`].
+    pub fn newly_empty_dirs(&self) -> Vec<PathBuf> {
+        let deleted: FxHashSet<&Path> = self.deleted_files.iter().map(PathBuf::as_path).collect();
+        let mut candidates: Vec<&Path> = self.deleted_files.iter()
+            .flat_map(|path| path.ancestors().skip(1))
+            .filter(|dir| dir.starts_with(&self.path2) && *dir != self.path2 && !deleted.contains(dir))
+            .collect::<FxHashSet<_>>()
+            .into_iter()
+            .collect();
+        candidates.sort_by(|a, b| b.components().count().cmp(&a.components().count()).then_with(|| a.cmp(b)));
+
+        let mut empty: Vec<PathBuf> = vec![];
+        let mut gone = deleted;
+        for dir in candidates {
+            let becomes_empty = fs::read_dir(dir).is_ok_and(|mut entries| {
+                entries.all(|entry| entry.is_ok_and(|entry| gone.contains(entry.path().as_path())))
+            });
+            if becomes_empty {
+                gone.insert(dir);
+                empty.push(dir.to_path_buf());
             }
+        }
+        empty
+    }
 
-            let path_without_prefix = entry.path().strip_prefix(path1)?;
-            let file_in_second_path = path2.join(path_without_prefix);
-            if !file_in_second_path.is_file() {
-                final_object.new_files.push(entry.path().to_path_buf());
+    /// Plans turning `path2` into a copy of `path1` without touching any file, e.g. for showing
+    /// the steps and asking for confirmation before applying them. The operations are the ones of
+    /// [`FolderCompare::as_operations`] in the same order, each along with the file it copies from
+    /// `path1` and the file it writes or removes in `path2`. Usually `path1` and `path2` are the
+    /// compared directories, but any directories with the same layout can be passed, e.g. a
+    /// staging copy of the second directory.
+    pub fn plan_sync(&self, path1: &Path, path2: &Path) -> Vec<PlannedOp> {
+        let relative = |path: &PathBuf, root: &Path| path.strip_prefix(root).unwrap_or(path).to_path_buf();
+        self.as_operations().into_iter().map(|op| {
+            let (source, destination) = match op {
+                SyncOp::Create(ref path) | SyncOp::Overwrite(ref path) => {
+                    let relative = relative(path, &self.path1);
+                    (Some(path1.join(&relative)), path2.join(relative))
+                }
+                SyncOp::Delete(ref path) => (None, path2.join(relative(path, &self.path2))),
+            };
+            PlannedOp { op, source, destination }
+        }).collect()
+    }
+
+    /// Writes the result to `writer` the way `diff -rq` reports differences, one line per entry
+    /// ordered like [`FolderCompare::events`], e.g. for scripts parsing the output of `diff`:
+    ///
+    ///```text
+    /// Only in /tmp/b: old.abc
+    /// Only in /tmp/a: test.abc
+    /// Files /tmp/a/test.xls and /tmp/b/test.xls differ
+    ///```
+    ///
+    /// New entries are reported as only in [`FolderCompare::path1`], deleted ones as only in
+    /// [`FolderCompare::path2`] and changed files as differing. Deleted entries are only known if
+    /// the comparison ran with [`CompareOptions::detect_deleted`], so set it to get every line
+    /// `diff -rq` would write. Like `diff`, a directory only existing on one side is reported once
+    /// instead of every entry within it, which is looked up in the other directory. Unchanged
+    /// entries aren't written. Paths that aren't valid UTF-8 are written lossily.
+    pub fn write_diff_rq<W: io::Write>(&self, writer: W) -> Result<(), Error> {
+        diff_rq::write_diff_rq(self, writer)
+    }
+
+    /// Writes the result to `writer` in the format of `rsync --itemize-changes`, one line per entry
+    /// ordered like [`FolderCompare::events`], e.g. for reports familiar to rsync users:
+    ///
+    ///```text
+    /// *deleting   old.abc
+    /// .f..tp..... same.abc
+    /// >f+++++++++ test.abc
+    /// >fcst...... test.xls
+    ///```
+    ///
+    /// New entries are marked with `+`, deleted ones with `*deleting` and changed files with `c`.
+    /// Sizes (`s`) and modification times (`t`) are read from both directories, permissions (`p`)
+    /// as well as owner and group (`o`, `g`) are only marked where the comparison checked them, see
+    /// [`CompareOptions::compare_permissions`] and [`CompareOptions::compare_ownership`]. Unchanged
+    /// files are only written if one of these differs. Paths are relative, directories end with a
+    /// `/`, and paths that aren't valid UTF-8 are written lossily.
+    pub fn write_itemized<W: io::Write>(&self, writer: W) -> Result<(), Error> {
+        itemize::write_itemized(self, writer)
+    }
+
+    /// Classifies the files at the `relative` paths within `path1` and `path2`, e.g. paths
+    /// collected with a walker of your own, without walking either directory. Files present in
+    /// both directories are compared like [`FolderCompare::with_options`] does, files only present
+    /// in `path1` are new and files only present in `path2` are deleted with
+    /// [`CompareOptions::detect_deleted`]. Paths of directories and of files missing in both are
+    /// skipped, and so are paths listed more than once. Excluded patterns are matched against the
+    /// full paths as usual.
+    ///
+    /// Only the listed files are looked at, so options running passes over the whole trees, like
+    /// [`CompareOptions::detect_same_content`] or
+    /// [`CompareOptions::report_skipped_symlinks`], don't apply.
+    ///
+    /// # Example
+    ///
+    ///```no_run
+    /// use std::path::Path;
+    /// use folder_compare::{CompareOptions, FolderCompare};
+    ///
+    /// let paths = vec!["src/main.rs", "Cargo.toml"];
+    /// let result = FolderCompare::from_iter(Path::new("/tmp/a"), Path::new("/tmp/b"), paths, &vec![], &CompareOptions::default()).unwrap();
+    ///```
+    ///
+    pub fn from_iter<I, P>(path1: &Path, path2: &Path, relative: I, excluded: &Vec<String>, options: &CompareOptions) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        Comparator::new(excluded, options.clone())?.compare_paths(path1, path2, relative)
+    }
+
+    /// Compares the directory at `path1` with the state recorded in a `prior` [`Manifest`] and
+    /// returns the result along with a manifest of the current state for the next run.
+    ///
+    /// Files whose size and modification time match the ones recorded are trusted to be unchanged
+    /// and not read at all, only the other files are hashed. This makes repeated comparisons of
+    /// mostly static trees a lot faster, but misses changes that keep both size and modification
+    /// time, just like `rsync --times` or build systems relying on timestamps do.
+    ///
+    /// New, changed and unchanged files are listed with their full path under `path1`, deleted
+    /// files with their path relative to it, as recorded in the manifest.
+    pub fn recompare(path1: &Path, prior: &Manifest, excluded: &Vec<String>) -> Result<(Self, Manifest), Error> {
+        manifest::recompare(path1, prior, excluded)
+    }
+
+    /// Compares two [`FileSource`]s instead of two directories, e.g. the entries of two archives.
+    /// Files are matched by their relative paths within the sources, which are also the paths
+    /// stored in the result. `excluded` is matched against these relative paths.
+    ///
+    /// Where both sources report [`FileSource::metadata`], files of different sizes are changed
+    /// and files with the same size and modification time are unchanged without reading either
+    /// of them. Only the remaining files are read from both sources and hashed, so comparing
+    /// against a remote source transfers the content of those files only. Like
+    /// [`FolderCompare::recompare`] this misses changes that keep both size and modification time.
+    /// Files listed by `first` which it then can't open are listed in [`FolderCompare::errors`].
+    pub fn from_sources(first: &mut dyn FileSource, second: &mut dyn FileSource, excluded: &Vec<String>) -> Result<Self, Error> {
+
+        let mut final_object = FolderCompare::default();
+        let set = RegexSet::new(excluded)?;
+
+        for path in first.files()? {
+            if set.matches(&path.to_string_lossy()).matched_any() {
                 continue;
             }
 
-            let second_file = file_in_second_path.to_path_buf().clone();
+            match (first.metadata(&path)?, second.metadata(&path)?) {
+                (Some(a), Some(b)) if a.size != b.size => {
+                    final_object.changed_files.push(path);
+                    continue;
+                }
+                (Some(a), Some(b)) if a.modified.zip(b.modified).is_some_and(|(m1, m2)| times_match(m1, m2, DEFAULT_MTIME_GRANULARITY)) => {
+                    final_object.unchanged_files.push(path);
+                    continue;
+                }
+                _ => {}
+            }
 
-            let buffer = &mut vec![];
-            File::open(entry.path())?.read_to_end(buffer)?;
-            let mut hasher = FxHasher::default();
-            hasher.write(buffer);
-            let buffer2 = &mut vec![];
-            File::open(second_file)?.read_to_end(buffer2)?;
-            let mut hasher2 = FxHasher::default();
-            hasher2.write(buffer2);
+            let second_hash = match second.open(&path)? {
+                None => {
+                    final_object.new_files.push(path);
+                    continue;
+                }
+                Some(reader) => hash_reader(reader)?,
+            };
+            let first_hash = match first.open(&path)? {
+                None => {
+                    let error = io::Error::new(io::ErrorKind::NotFound, "listed file can't be opened");
+                    final_object.errors.push(FileError::new(&path, &error));
+                    continue;
+                }
+                Some(reader) => hash_reader(reader)?,
+            };
 
-            if hasher.finish() == hasher2.finish() {
-                final_object.unchanged_files.push(entry.into_path());
+            if first_hash == second_hash {
+                final_object.unchanged_files.push(path);
             } else {
-                final_object.changed_files.push(entry.into_path());
+                final_object.changed_hashes.push((path.clone(), first_hash, second_hash));
+                final_object.changed_files.push(path);
             }
         }
 
-
         Ok(final_object)
     }
 }
 
+/// The granularity times are compared with if [`CompareOptions::mtime_granularity`] is unset.
+pub(crate) const DEFAULT_MTIME_GRANULARITY: Duration = Duration::from_micros(1);
+
+/// Checks whether the times `a` and `b` are less than `granularity` apart, or equal for a zero
+/// granularity.
+pub(crate) fn times_match(a: SystemTime, b: SystemTime, granularity: Duration) -> bool {
+    let distance = a.duration_since(b).unwrap_or_else(|e| e.duration());
+    distance.is_zero() || distance < granularity
+}
+
+/// Groups `paths` by their parent directory. Paths without a parent are grouped under an empty path.
+fn group_by_parent(paths: &[PathBuf]) -> HashMap<PathBuf, Vec<PathBuf>> {
+    let mut groups: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+    for path in paths {
+        let parent = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        groups.entry(parent).or_default().push(path.clone());
+    }
+    groups
+}
+
+/// Appends all `entries` to `target` that aren't part of it yet.
+fn extend_unique<T: Clone + Eq + Hash>(target: &mut Vec<T>, entries: Vec<T>) {
+    let mut seen: FxHashSet<T> = target.iter().cloned().collect();
+    target.extend(entries.into_iter().filter(|entry| seen.insert(entry.clone())));
+}
+
 /// Wrapper for possible errors
 #[derive(Debug)]
 pub enum Error {
     Io(std::io::Error),
     Regex(regex::Error),
     StripPrefix(std::path::StripPrefixError),
+    /// One of the directories to compare doesn't exist or is something else, e.g. a file.
+    NotADirectory(PathBuf),
+    /// Two entries of the same directory whose paths only differ in case, found with
+    /// [`CompareOptions::case_insensitive_paths`].
+    CaseCollision(PathBuf, PathBuf),
+    /// An option of [`CompareOptions`] set to a value it can't take, named by its field, e.g. a
+    /// [`CompareOptions::change_threshold`] outside of 0 to 1.
+    InvalidOption(&'static str),
+    #[cfg(feature = "archive")]
+    Zip(zip::result::ZipError),
+    #[cfg(feature = "ssh")]
+    Ssh(ssh2::Error),
+    #[cfg(feature = "git")]
+    Git(git2::Error),
+    #[cfg(feature = "notify")]
+    Notify(notify::Error),
 }
 
 impl From<std::io::Error> for Error {
@@ -131,4 +879,32 @@ impl From<std::path::StripPrefixError> for Error {
     fn from(e: std::path::StripPrefixError) -> Error {
         Error::StripPrefix(e)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(feature = "archive")]
+impl From<zip::result::ZipError> for Error {
+    fn from(e: zip::result::ZipError) -> Error {
+        Error::Zip(e)
+    }
+}
+
+#[cfg(feature = "ssh")]
+impl From<ssh2::Error> for Error {
+    fn from(e: ssh2::Error) -> Error {
+        Error::Ssh(e)
+    }
+}
+
+#[cfg(feature = "git")]
+impl From<git2::Error> for Error {
+    fn from(e: git2::Error) -> Error {
+        Error::Git(e)
+    }
+}
+
+#[cfg(feature = "notify")]
+impl From<notify::Error> for Error {
+    fn from(e: notify::Error) -> Error {
+        Error::Notify(e)
+    }
+}
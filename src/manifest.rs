@@ -0,0 +1,143 @@
+use fxhash::{FxHashMap, FxHashSet};
+use regex::RegexSet;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::hash::ContentHasher;
+use crate::walk::files_to_compare;
+use crate::{times_match, CompareOptions, Error, FolderCompare, DEFAULT_MTIME_GRANULARITY};
+
+/// A record of the files in a directory at some point in time, with the size, modification time
+/// and hash of every file. A manifest can be saved with [`Manifest::write_to`] and later be used
+/// to find out what changed since with [`FolderCompare::recompare`].
+///
+/// # Example
+///
+///```no_run
+/// use std::fs::File;
+/// use std::io::BufReader;
+/// use std::path::Path;
+/// use folder_compare::{FolderCompare, Manifest};
+///
+/// let manifest = Manifest::create(Path::new("/tmp/a"), &vec![]).unwrap();
+/// manifest.write_to(File::create("/tmp/a.manifest").unwrap()).unwrap();
+///
+/// let prior = Manifest::read_from(BufReader::new(File::open("/tmp/a.manifest").unwrap())).unwrap();
+/// let (result, manifest) = FolderCompare::recompare(Path::new("/tmp/a"), &prior, &vec![]).unwrap();
+///```
+///
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    pub entries: Vec<ManifestEntry>,
+}
+
+/// A single file recorded in a [`Manifest`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// The path of the file relative to the recorded directory.
+    pub path: PathBuf,
+    /// The size of the file in bytes.
+    pub size: u64,
+    /// The time the file was last modified.
+    pub modified: SystemTime,
+    /// The hash of the content of the file, as computed by [`hash_file`](crate::hash_file).
+    pub hash: u64,
+}
+
+impl Manifest {
+    /// Records all files in the directory at `path`, leaving out everything matching the
+    /// `excluded` patterns.
+    pub fn create(path: &Path, excluded: &Vec<String>) -> Result<Self, Error> {
+        Ok(recompare(path, &Manifest::default(), excluded)?.1)
+    }
+
+    /// Writes the manifest to `writer` as text, one file per line. Paths that aren't valid UTF-8
+    /// are written lossily and can't be matched after reading the manifest back in.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        for entry in &self.entries {
+            let modified = entry.modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+            writeln!(
+                writer,
+                "{:016x}\t{}\t{}.{:09}\t{}",
+                entry.hash,
+                entry.size,
+                modified.as_secs(),
+                modified.subsec_nanos(),
+                entry.path.to_string_lossy()
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Reads a manifest written by [`Manifest::write_to`].
+    pub fn read_from<R: BufRead>(reader: R) -> Result<Self, Error> {
+        let mut manifest = Manifest::default();
+        for line in reader.lines() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            manifest.entries.push(parse_entry(&line).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("invalid manifest line: {}", line))
+            })?);
+        }
+        Ok(manifest)
+    }
+}
+
+/// Parses a single line written by [`Manifest::write_to`].
+fn parse_entry(line: &str) -> Option<ManifestEntry> {
+    let mut fields = line.splitn(4, '\t');
+    let hash = u64::from_str_radix(fields.next()?, 16).ok()?;
+    let size = fields.next()?.parse().ok()?;
+    let (secs, nanos) = fields.next()?.split_once('.')?;
+    let modified = UNIX_EPOCH + Duration::new(secs.parse().ok()?, nanos.parse().ok()?);
+    let path = PathBuf::from(fields.next()?);
+    Some(ManifestEntry { path, size, modified, hash })
+}
+
+/// Compares the directory at `path1` with the state recorded in `prior`, see
+/// [`FolderCompare::recompare`].
+pub(crate) fn recompare(path1: &Path, prior: &Manifest, excluded: &Vec<String>) -> Result<(FolderCompare, Manifest), Error> {
+    let set = RegexSet::new(excluded)?;
+    let options = CompareOptions::default();
+    let known: FxHashMap<&Path, &ManifestEntry> = prior.entries.iter().map(|entry| (entry.path.as_path(), entry)).collect();
+
+    let mut hasher = ContentHasher::new();
+    let mut result = FolderCompare {
+        path1: path1.to_path_buf(),
+        ..Default::default()
+    };
+    let mut manifest = Manifest::default();
+
+    for entry in files_to_compare(path1, &set, &options) {
+        let relative = entry.path().strip_prefix(path1)?.to_path_buf();
+        let metadata = entry.metadata()?;
+        let (size, modified) = (metadata.len(), metadata.modified()?);
+
+        let prior_entry = known.get(relative.as_path());
+        let hash = match prior_entry {
+            Some(prior_entry) if prior_entry.size == size && times_match(prior_entry.modified, modified, DEFAULT_MTIME_GRANULARITY) => prior_entry.hash,
+            _ => hasher.hash_file(entry.path(), &mut |_| {})?,
+        };
+        match prior_entry {
+            None => result.new_files.push(entry.path().to_path_buf()),
+            Some(prior_entry) if prior_entry.hash == hash => result.unchanged_files.push(entry.path().to_path_buf()),
+            Some(prior_entry) => {
+                result.changed_hashes.push((entry.path().to_path_buf(), hash, prior_entry.hash));
+                result.changed_files.push(entry.path().to_path_buf());
+            }
+        }
+        manifest.entries.push(ManifestEntry { path: relative, size, modified, hash });
+    }
+
+    let present: FxHashSet<&Path> = manifest.entries.iter().map(|entry| entry.path.as_path()).collect();
+    for prior_entry in &prior.entries {
+        if !present.contains(prior_entry.path.as_path()) {
+            result.deleted_files.push(prior_entry.path.clone());
+        }
+    }
+
+    Ok((result, manifest))
+}
@@ -0,0 +1,661 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use walkdir::WalkDir;
+
+use crate::FileStatus;
+
+/// Options for tuning the comparison done by [`FolderCompare::with_options`].
+///
+/// All options default to the behavior of [`FolderCompare::new`], so only the ones that differ
+/// need to be set:
+///
+///```
+/// use folder_compare::{CompareOptions, Symlinks};
+///
+/// let options = CompareOptions {
+///     symlinks: Symlinks::FollowDirectories,
+///     ..Default::default()
+/// };
+///```
+///
+/// [`FolderCompare::with_options`]: crate::FolderCompare::with_options
+/// [`FolderCompare::new`]: crate::FolderCompare::new
+#[derive(Debug, Default, Clone)]
+pub struct CompareOptions {
+    /// How the content of files existing in both directories is compared.
+    pub mode: CompareMode,
+    /// How symbolic links found in the first directory are handled.
+    pub symlinks: Symlinks,
+    /// Lists the symbolic links left out because of [`symlinks`](CompareOptions::symlinks) in
+    /// [`FolderCompare::skipped_symlinks`](crate::FolderCompare::skipped_symlinks), e.g. to tell
+    /// them apart from excluded files when files seem to be missing. This walks the directories
+    /// once more.
+    pub report_skipped_symlinks: bool,
+    /// Patterns excluding entries of the first directory only, used instead of the excluded
+    /// patterns passed to the comparison unless empty. A file excluded on one side is compared as
+    /// if it didn't exist there, so its counterpart shows up as new or deleted, e.g. a `.gitignore`
+    /// excluded only in a source tree is still listed as deleted when comparing a build output
+    /// against it with [`detect_deleted`](CompareOptions::detect_deleted).
+    pub excluded_first: Vec<String>,
+    /// Patterns excluding entries of the second directory only, used instead of the excluded
+    /// patterns passed to the comparison unless empty, see
+    /// [`excluded_first`](CompareOptions::excluded_first).
+    pub excluded_second: Vec<String>,
+    /// Leaves files only existing in the first directory out of the result instead of listing
+    /// them in [`FolderCompare::new_files`](crate::FolderCompare::new_files), so only the files
+    /// existing in both directories are classified as changed or unchanged. This suits asking what
+    /// changed among the files both directories share. New files don't count towards
+    /// [`limit`](CompareOptions::limit) then and
+    /// [`detect_name_only_differences`](CompareOptions::detect_name_only_differences) finds
+    /// nothing, as it pairs new files.
+    pub changes_only: bool,
+    /// Also walks the second directory to find files missing in the first one and lists them in
+    /// [`FolderCompare::deleted_files`](crate::FolderCompare::deleted_files).
+    pub detect_deleted: bool,
+    /// Lists special files like FIFOs, sockets or device nodes in
+    /// [`FolderCompare::unexpected_entries`](crate::FolderCompare::unexpected_entries) instead of
+    /// silently skipping them.
+    pub strict_regular: bool,
+    /// Compares the owner and group (uid and gid) of files existing in both directories and lists
+    /// the ones that differ in
+    /// [`FolderCompare::ownership_changed`](crate::FolderCompare::ownership_changed). Only
+    /// supported on Unix, elsewhere this does nothing. Files whose metadata can't be read are
+    /// treated as unchanged.
+    pub compare_ownership: bool,
+    /// Compares the permissions of files existing in both directories and lists the ones that
+    /// differ in
+    /// [`FolderCompare::permissions_changed`](crate::FolderCompare::permissions_changed). On Unix
+    /// the mode bits are compared, elsewhere only the read-only flag. Together with
+    /// [`compare_ownership`](CompareOptions::compare_ownership) this verifies that a restore
+    /// preserved the metadata of the files along with their content.
+    pub compare_permissions: bool,
+    /// Only compares these mode bits with
+    /// [`compare_permissions`](CompareOptions::compare_permissions), e.g. `Some(0o111)` for
+    /// whether files are executable, ignoring differences caused by different umasks. All mode
+    /// bits are compared if unset. Only used on Unix.
+    pub permission_mask: Option<u32>,
+    /// Compares the creation times of files existing in both directories and lists the ones with
+    /// identical content but a different creation time in
+    /// [`FolderCompare::created_changed`](crate::FolderCompare::created_changed). Creation times
+    /// are available on Windows, macOS and the BSDs, and on Linux for file systems supporting
+    /// `statx`. Where they aren't available the check is skipped. The inode change time (`ctime`)
+    /// of Unix isn't taken instead, as it changes with every update of a file's metadata and says
+    /// nothing about when the file was created.
+    pub compare_created: bool,
+    /// Times closer to each other than this are treated as equal when comparing modification times
+    /// with [`CompareMode::Newer`] and [`strict_metadata`](Self::strict_metadata) or creation times
+    /// with [`compare_created`](Self::compare_created). Set it to two seconds when comparing
+    /// against FAT file systems, which store times with a resolution of two seconds. Defaults to
+    /// one microsecond if unset, which absorbs the 100 nanosecond resolution of NTFS and the
+    /// nanoseconds lost when tools copy times with microsecond precision, but no real change. A
+    /// zero granularity compares times exactly. The default granularity is also used where
+    /// modification times are compared without options, i.e. by
+    /// [`FolderCompare::recompare`](crate::FolderCompare::recompare),
+    /// [`FolderCompare::from_sources`](crate::FolderCompare::from_sources) and the `t` flag of
+    /// [`FolderCompare::write_itemized`](crate::FolderCompare::write_itemized).
+    pub mtime_granularity: Option<Duration>,
+    /// Counts files with identical content as changed unless their metadata is identical as well,
+    /// for a strict notion of identical files. Exactly two attributes are folded in: the
+    /// modification time, compared with [`mtime_granularity`](Self::mtime_granularity), and the
+    /// permissions, compared like [`compare_permissions`](Self::compare_permissions) does within
+    /// [`permission_mask`](Self::permission_mask). Ownership, creation times and extended
+    /// attributes aren't. Every differing attribute is listed in
+    /// [`FolderCompare::metadata_differences`](crate::FolderCompare::metadata_differences).
+    pub strict_metadata: bool,
+    /// Compares which of the files existing in both directories are hard linked with each other
+    /// and lists the ones linked with a different set of files in each directory in
+    /// [`FolderCompare::hardlink_structure_changed`](crate::FolderCompare::hardlink_structure_changed),
+    /// e.g. two files that are hard links in the first directory but separate copies in the
+    /// second one. Only supported on Unix, elsewhere this does nothing.
+    pub compare_hardlinks: bool,
+    /// Reads every file at most once per comparison, even if it's reachable at several paths.
+    /// Hashes are kept by inode, so hard links to a file already hashed reuse its hash, and files
+    /// at the same relative path in both directories sharing an inode are unchanged without
+    /// reading either of them. This saves a lot of reading for trees sharing files through hard
+    /// links, like snapshots made with `cp -al` or `rsync --link-dest`, without changing any
+    /// classification. Copies of a file with their own inode are still read: telling that a copy
+    /// is byte-identical to a file hashed earlier takes reading the copy, and reusing the hash of
+    /// an earlier file of the same size instead would list different content as unchanged. Keeps
+    /// a hash per file in memory and only supported on Unix, elsewhere this does nothing.
+    pub dedupe_reads: bool,
+    /// Leaves out directories with exactly one of these names at any depth, e.g. `node_modules`
+    /// or `.git`, along with everything below them. Unlike excluded patterns, which are matched
+    /// against the whole path of every entry, this compares the name of directories only and
+    /// doesn't walk the directories left out at all, which saves a lot of time for huge ones.
+    pub prune_dir_names: Vec<String>,
+    /// Only walks these subdirectories of both directories, given relative to them, e.g.
+    /// `src` and `config`, and compares nothing outside of them. Unlike excluding everything else
+    /// with patterns, the rest of the trees isn't walked at all. Subdirectories missing in a
+    /// directory are skipped there, so their files show up as new or deleted. Overlapping
+    /// subdirectories like `src` and `src/bin` are walked once, as part of the outer one, so no
+    /// file is listed twice. All of both directories are walked if this is empty.
+    pub subpaths: Vec<PathBuf>,
+    /// Treats every directory as a bag of files and lists the directories existing in both
+    /// directories whose files have a different multiset of contents in
+    /// [`FolderCompare::content_set_changed`](crate::FolderCompare::content_set_changed). Names
+    /// don't matter, so a directory whose files were only renamed still matches, while a missing
+    /// or duplicated copy of a file doesn't. Only files directly within a directory count for it.
+    /// This hashes every file of both directories once more, so it is off by default.
+    pub compare_content_sets: bool,
+    /// Keeps the walks from descending into directories on other file systems than the compared
+    /// directories, e.g. mount points of `/proc` or network shares within the tree.
+    pub same_file_system: bool,
+    /// Only compares the entries of one shard of the tree, given as `(index, total)` with `index`
+    /// counting from 0, e.g. for spreading a huge comparison over several machines. Every entry is
+    /// assigned to a shard by a hash of its relative path, so each entry is handled by exactly one
+    /// shard and merging the results of all shards with
+    /// [`FolderCompare::merge`](crate::FolderCompare::merge) gives the full result. All shards
+    /// still walk the whole tree, and entries that can't be read while walking are reported by
+    /// every shard. The hash depends on the pointer width, so all shards have to run on machines
+    /// with the same one. An `index` not below `total`, which includes a `total` of 0, is rejected
+    /// with [`Error::InvalidOption`](crate::Error::InvalidOption).
+    pub shard: Option<(usize, usize)>,
+    /// Which kinds of entries are compared, only files by default.
+    pub entry_kinds: EntryKinds,
+    /// Treats both `/` and `\` as path separators when looking up the counterpart of a file in
+    /// the other directory, so `a\b\c` and `a/b/c` are matched. This is useful if one side
+    /// was created from a listing produced on Windows. It only affects the matching, the paths
+    /// stored in the result are left as they were found.
+    pub normalize_separators: bool,
+    /// Walks both directories side by side, sorted by file name, and classifies the files by
+    /// merging the two sorted walks instead of looking up every file of the first directory in the
+    /// second one. Only the current entry of each walk is held in memory and no extra walk is
+    /// needed for [`detect_deleted`](CompareOptions::detect_deleted), which suits huge trees. Files are matched by their exact relative paths, so
+    /// [`normalize_separators`](CompareOptions::normalize_separators) has no effect in this mode.
+    pub merge_join: bool,
+    /// Looks for files that only differ in the case or Unicode normalization of their names
+    /// between both directories, while their content is identical, and lists them in
+    /// [`FolderCompare::name_only_differences`](crate::FolderCompare::name_only_differences).
+    /// This builds an index of all files only existing in the second directory and hashes every
+    /// candidate pair.
+    pub detect_name_only_differences: bool,
+    /// Pairs entries of both directories whose relative paths only differ in case, like
+    /// `Foo.txt` and `foo.txt`, instead of listing them as new and deleted, e.g. for comparing a
+    /// tree from Windows or macOS with one from Linux. Paths are folded to lowercase after
+    /// normalizing their Unicode representation, so composed and decomposed accents match as
+    /// well. Entries without such a counterpart are matched exactly as usual. The comparison
+    /// fails with [`Error::CaseCollision`](crate::Error::CaseCollision) if two entries of the
+    /// same directory fold to the same path. Both directories are walked once more up front to
+    /// index their entries, which are kept in memory. Not used with
+    /// [`merge_join`](CompareOptions::merge_join).
+    pub case_insensitive_paths: bool,
+    /// Looks for files with identical content at different relative paths in both directories,
+    /// no matter where they live in each tree, and lists them in
+    /// [`FolderCompare::same_content_different_path`](crate::FolderCompare::same_content_different_path).
+    /// This is useful for trees that were reorganized differently. It hashes every file of both
+    /// directories once more and keeps an index of all hashes of the second one in memory, so it
+    /// is off by default.
+    pub detect_same_content: bool,
+    /// Mixes the file name of every file into its hash where files are matched by content alone,
+    /// so identical content under a different name counts as different. With
+    /// [`detect_same_content`](Self::detect_same_content) only files moved to another directory
+    /// under the same name are paired, and with
+    /// [`compare_content_sets`](Self::compare_content_sets) renaming a file changes the set of its
+    /// directory. Only the file name is hashed, not the whole relative path, as files at the same
+    /// relative path in both directories are compared with each other anyway. Off by default,
+    /// which matches files by their content only.
+    pub hash_file_names: bool,
+    /// Compares the content of text files ignoring the case of ASCII letters, so `TRUE` and `true`
+    /// are considered equal. Binary files, recognized by a NUL byte close to their start, are
+    /// compared as they are. This is an aggressive normalization that hides real changes in
+    /// case-sensitive formats, so it is off by default.
+    pub ignore_case: bool,
+    /// Leaves lines matching this regular expression out when comparing the content of text
+    /// files, so files only differing in volatile lines like `Generated: 2024-01-01` are taken as
+    /// unchanged. Lines are matched without their line ending. Binary files, recognized like with
+    /// [`ignore_case`](CompareOptions::ignore_case), are compared as they are. An invalid pattern
+    /// fails the comparison right away, like invalid excluded patterns do.
+    pub ignore_line_pattern: Option<String>,
+    /// Leaves NUL bytes at the end of files out when comparing their content, so files only
+    /// differing in the padding some tools add up to block boundaries are taken as unchanged. NUL
+    /// bytes followed by other content are compared as usual. The padding is still read, so this
+    /// only costs checking every chunk for its last non-NUL byte. Doesn't apply to
+    /// [`sample_offsets`](CompareOptions::sample_offsets), lines of
+    /// [`ignore_line_pattern`](CompareOptions::ignore_line_pattern) or the sizes compared by
+    /// [`empty_files_match`](CompareOptions::empty_files_match) and [`CompareMode::Size`].
+    pub ignore_trailing_nulls: bool,
+    /// Only classifies files with differing content as changed if more than this share of their
+    /// blocks differ, given as a fraction between 0 and 1, and as unchanged otherwise. Files are
+    /// compared in blocks of 4 KiB at the same positions, blocks past the end of the smaller file
+    /// count as differing. This keeps formats with volatile headers or metadata blocks from
+    /// showing up as changed. Both files of every pair with differing hashes are read once more to
+    /// compute the share, which about doubles the cost of comparing changed files. Inserting or
+    /// removing bytes shifts all following blocks, so such files usually exceed the threshold.
+    /// Thresholds outside of 0 to 1, including NaN, are rejected with
+    /// [`Error::InvalidOption`](crate::Error::InvalidOption). Files that can't be read for this
+    /// are handled like files that can't be hashed, see
+    /// [`continue_on_error`](CompareOptions::continue_on_error).
+    pub change_threshold: Option<f64>,
+    /// Checks whether new, changed and deleted files are binary and lists the binary ones in
+    /// [`FolderCompare::binary_files`](crate::FolderCompare::binary_files), e.g. for showing a diff
+    /// of text files only. A file is taken as binary if a NUL byte shows up within its first 8000
+    /// bytes, so only this prefix is read.
+    pub detect_binary: bool,
+    /// Compares files by their size and blocks of 4 KiB sampled at these offsets instead of their
+    /// whole content. Offsets are fractions of the file size, so `Some(vec![0.0, 0.5, 1.0])`
+    /// samples the start, the middle and the end of every file. This is a lot faster for huge
+    /// files like videos, but it is probabilistic: Changes between the samples that keep the size
+    /// go unnoticed. [`ignore_case`](CompareOptions::ignore_case) and
+    /// [`ignore_line_pattern`](CompareOptions::ignore_line_pattern) don't apply to the samples.
+    pub sample_offsets: Option<Vec<f64>>,
+    /// Only compares files whose size in bytes lies within this range, all other files are left
+    /// out as if they were excluded. This applies to the files of both directories. For example
+    /// `Some(1..u64::MAX)` skips empty files and `Some(0..1 << 30)` skips files of 1 GiB and more.
+    pub size_filter: Option<Range<u64>>,
+    /// Classifies two files at the same relative path as unchanged if both report a size of zero
+    /// bytes and as changed if only one of them does, without opening them. This saves a read
+    /// attempt for every placeholder file in trees with lots of them. Files reporting a size of
+    /// zero while still having content, like some files under `/proc`, are then taken as empty,
+    /// which is why this is off by default. The classification of empty files doesn't change, see
+    /// [`CompareMode::Content`].
+    pub empty_files_match: bool,
+    /// Lists files whose counterpart in the second directory is shorter and holds exactly the start
+    /// of their content in [`FolderCompare::partial`](crate::FolderCompare::partial) instead of
+    /// the changed files, e.g. for resuming interrupted downloads into the second directory. Only
+    /// the shorter length is read from both files to check this. Partial files are passed to
+    /// [`ResultSink::on_partial`](crate::ResultSink::on_partial) and count towards
+    /// [`limit`](CompareOptions::limit), but aren't passed to
+    /// [`reclassify`](CompareOptions::reclassify) and get no
+    /// [`collect_metadata`](CompareOptions::collect_metadata) entries.
+    pub detect_partial: bool,
+    /// Lists every classified entry along with its status, size and modification time in
+    /// [`FolderCompare::entries`](crate::FolderCompare::entries), in addition to the lists by
+    /// status, so callers needing the metadata don't have to read it once more. The metadata is read
+    /// when the entry is classified, for files existing in both directories from the first one.
+    pub collect_metadata: bool,
+    /// Patterns for files that are compared and listed like all other files, but are never
+    /// marked as changed. Files existing in both directories whose path matches one of these
+    /// patterns are taken as unchanged without reading them, which suits generated files that
+    /// should show up in the result without failing a check. New and deleted files are still
+    /// listed as such, and [`strict_metadata`](CompareOptions::strict_metadata) doesn't apply to
+    /// them. The patterns are matched like the excluded ones, and excluded files are
+    /// left out entirely even if they match a pattern here as well.
+    /// [`reclassify`](CompareOptions::reclassify) is applied afterwards and may still override
+    /// the classification.
+    pub soft_excluded: Vec<String>,
+    /// Called for every entry after it was classified, allowing to override the classification
+    /// with rules of your own. The path passed is the one stored in the result.
+    pub reclassify: Option<Reclassify>,
+    /// Stops the comparison as soon as this many differences (new, changed and deleted entries
+    /// combined) were found and flags the result as
+    /// [`truncated`](crate::FolderCompare::truncated). Useful for a quick look at a sample of the
+    /// differences.
+    pub limit: Option<usize>,
+    /// Stops the comparison once this many bytes were read for hashing files and flags the result
+    /// as [`truncated`](crate::FolderCompare::truncated), e.g. for checking a sample of a huge tree
+    /// within a fixed I/O budget. The file being read when the budget runs out is still compared
+    /// completely, so slightly more may be read. Which files make it into the budget depends on the
+    /// order they are visited in, see [`order`](CompareOptions::order). Files classified without
+    /// reading them, e.g. by size, don't count.
+    pub max_bytes_read: Option<u64>,
+    /// Keeps comparing when a file existing in both directories can't be read, e.g. because of a
+    /// disk error or missing permissions, instead of failing the whole comparison. The file is
+    /// listed in [`FolderCompare::errors`](crate::FolderCompare::errors) and left out of all
+    /// other lists.
+    pub continue_on_error: bool,
+    /// What happens with directories and other entries that can't be read while walking, e.g.
+    /// subdirectories only root may open. By default they are listed in
+    /// [`FolderCompare::errors`](crate::FolderCompare::errors), so it shows that the comparison
+    /// didn't cover the whole tree.
+    pub walk_errors: WalkErrors,
+    /// Checks that the counterpart looked up for every entry stays within the other directory
+    /// once symbolic links and `..` are resolved, which hardens comparisons of untrusted trees
+    /// against crafted links like `sub -> ../..`. An entry whose counterpart escapes is left out
+    /// of all lists and fails the comparison with a `PermissionDenied` error, or is listed in
+    /// [`FolderCompare::errors`](crate::FolderCompare::errors) with
+    /// [`continue_on_error`](CompareOptions::continue_on_error). Paths passed to
+    /// [`FolderCompare::from_iter`](crate::FolderCompare::from_iter) are checked in both
+    /// directories, so absolute paths or paths with `..` can't reach files outside of them.
+    /// Comparisons with [`merge_join`](CompareOptions::merge_join) don't look counterparts up and
+    /// aren't checked.
+    pub confine_to_roots: bool,
+    /// How many times reading a file is retried after a transient error before the error is
+    /// listed or fails the comparison, which helps with flaky network shares. Only the error kinds
+    /// `Interrupted`, `TimedOut` and `WouldBlock` are retried, errors like `NotFound` or
+    /// `PermissionDenied` are not. The delay before each retry doubles, starting at 10 ms. A
+    /// retried file is read from the start again, so progress reports may count it twice.
+    pub io_retries: u32,
+    /// How long hashing a single file, retries included, may take before the file is given up on
+    /// and listed in [`FolderCompare::timed_out`](crate::FolderCompare::timed_out) instead, e.g.
+    /// so a hanging network share doesn't stall the whole comparison. Files are then hashed one
+    /// after another on a worker thread. Timed-out reads aren't cancelled: the worker keeps waiting
+    /// for its read in the background and the next file is hashed on a new worker. The bytes of a
+    /// file are reported as progress once it has been hashed.
+    pub per_file_timeout: Option<Duration>,
+    /// Caches the hash of every file compared in the extended attribute
+    /// `user.folder_compare.hash`, along with its modification time, and reuses it on later
+    /// comparisons as long as the modification time didn't change. This turns repeated
+    /// comparisons of unchanged files into metadata checks, but trusts the modification time just
+    /// like [`FolderCompare::recompare`](crate::FolderCompare::recompare) does. Cached hashes are
+    /// read in both directories, but only written for files in `path1`, so the second directory,
+    /// usually the one verified against the first, isn't modified. Writing the cache needs write
+    /// permission on the files; files whose cache can't be written are simply hashed again next
+    /// time, and so are files modified while they were hashed. Only supported on Unix, and not
+    /// used with [`ignore_case`](CompareOptions::ignore_case),
+    /// [`ignore_line_pattern`](CompareOptions::ignore_line_pattern),
+    /// [`ignore_trailing_nulls`](CompareOptions::ignore_trailing_nulls) or
+    /// [`sample_offsets`](CompareOptions::sample_offsets), whose hashes differ.
+    #[cfg(feature = "xattr-cache")]
+    pub use_xattr_cache: bool,
+    /// Skips reading the holes of sparse files, like VM images or databases which are mostly
+    /// unallocated, and hashes them as the zeros they read as instead. A sparse file therefore
+    /// still matches a fully allocated copy with the same content. Holes are found with
+    /// `SEEK_DATA` and `SEEK_HOLE` on Linux and Android, elsewhere and on file systems without
+    /// support for them the files are read completely. Not used with
+    /// [`ignore_case`](CompareOptions::ignore_case),
+    /// [`ignore_line_pattern`](CompareOptions::ignore_line_pattern),
+    /// [`ignore_trailing_nulls`](CompareOptions::ignore_trailing_nulls) or
+    /// [`sample_offsets`](CompareOptions::sample_offsets). Available with the `sparse` feature.
+    #[cfg(feature = "sparse")]
+    pub sparse_files: bool,
+    /// Hashes files of at least this many bytes with BLAKE3, spreading the hashing of every
+    /// single file over all cores, while smaller files are hashed one by one as usual. This
+    /// speeds up trees dominated by a few huge files like VM images, as long as the storage reads
+    /// faster than a single core hashes. Files are read in chunks of 16 MiB, or
+    /// [`max_memory`](CompareOptions::max_memory) if smaller, and every chunk is hashed on the
+    /// global `rayon` thread pool, which starts one thread per core on first use. Files of
+    /// different sizes never have equal content, so mixing both hashes doesn't change any
+    /// classification, but the hashes reported e.g. in
+    /// [`FolderCompare::changed_hashes`](crate::FolderCompare::changed_hashes) differ from
+    /// [`hash_file`](crate::hash_file). Not used with [`ignore_case`](CompareOptions::ignore_case),
+    /// [`ignore_line_pattern`](CompareOptions::ignore_line_pattern),
+    /// [`ignore_trailing_nulls`](CompareOptions::ignore_trailing_nulls) or
+    /// [`sample_offsets`](CompareOptions::sample_offsets), and the hashes aren't cached with
+    /// `use_xattr_cache`. Available with the `blake3` feature.
+    #[cfg(feature = "blake3")]
+    pub parallel_hash_threshold: Option<u64>,
+    /// Caps the size in bytes of the buffer files are read into, which is 64 KiB otherwise. Files
+    /// are compared one after the other with a single buffer, which
+    /// [`detect_partial`](CompareOptions::detect_partial) splits in two halves and
+    /// [`parallel_hash_threshold`](CompareOptions::parallel_hash_threshold) hashes on several
+    /// threads without copying it, so the thread count doesn't multiply the memory used. Smaller
+    /// buffers mean more reads, which slows down the comparison. The buffer is kept at least 8
+    /// bytes large. The cap doesn't cover everything though: with
+    /// [`ignore_line_pattern`](CompareOptions::ignore_line_pattern) every line is held as a whole,
+    /// so a file without line breaks is read into memory completely, and with
+    /// [`per_file_timeout`](CompareOptions::per_file_timeout) every timed-out worker keeps its own
+    /// buffer until its read returns.
+    pub max_memory: Option<usize>,
+    /// The order in which the entries of each directory are visited, which is also the order of
+    /// the entries within the lists of the result. By default the order the file system returns
+    /// them in is used, which may differ between runs and platforms. Sorting needs all entries of
+    /// a directory to be read before the first one is compared, which costs some time and memory
+    /// for directories with a huge number of entries. Directories are still walked depth first,
+    /// the order only applies to entries sharing the same parent. With
+    /// [`merge_join`](CompareOptions::merge_join) entries are always sorted by file name and this
+    /// option is ignored.
+    pub order: Option<EntryOrder>,
+    /// Sorts the new, changed, unchanged and deleted files of the result once the comparison is
+    /// done, e.g. largest first for starting the biggest transfers of a sync early. Unlike
+    /// [`order`](CompareOptions::order) this sorts each list as a whole rather than the entries of
+    /// each directory, and doesn't affect the order the files are compared in. Sorting by size or
+    /// modification time reads the metadata of every listed file once more.
+    pub order_by: OrderBy,
+    /// Called with the `WalkDir` set up for walking each of the directories, allowing to configure
+    /// it further, e.g. with `max_depth` or `same_file_system`. The walk is already rooted at the
+    /// directory and set up according to the other options, which the closure can override.
+    /// [`merge_join`](CompareOptions::merge_join) and [`order`](CompareOptions::order) are applied
+    /// afterwards though. The closure is responsible for keeping the walk below the directory,
+    /// i.e. `min_depth` must stay at least 1, and for configuring both walks the same way.
+    pub configure_walk: Option<ConfigureWalk>,
+    /// Walks the directories with `jwalk`, which reads directories on several threads at once.
+    /// This speeds up trees with millions of entries where reading directories and metadata
+    /// rather than hashing content takes most of the time, especially combined with
+    /// [`CompareMode::Size`]. The entries found are the same as with the default walk, but
+    /// [`same_file_system`](CompareOptions::same_file_system) and
+    /// [`configure_walk`](CompareOptions::configure_walk) are ignored. Available with the
+    /// `parallel-walk` feature.
+    #[cfg(feature = "parallel-walk")]
+    pub parallel_walk: bool,
+    /// Stores all paths in the result relative to this directory instead of starting with the
+    /// compared directories, e.g. relative to a common ancestor of both or to a root used for
+    /// display. This includes [`FolderCompare::path1`](crate::FolderCompare::path1) and
+    /// [`FolderCompare::path2`](crate::FolderCompare::path2), so
+    /// [`FolderCompare::statuses`](crate::FolderCompare::statuses) keeps working. Paths outside of
+    /// this directory are kept as they are. The paths are rewritten once the comparison is done, a
+    /// [`ResultSink`](crate::ResultSink) still receives the paths as they were found.
+    pub display_base: Option<PathBuf>,
+}
+
+/// Handling of symbolic links found while walking the first directory.
+///
+/// When following links, links whose target doesn't exist are listed in
+/// [`FolderCompare::broken_symlinks`](crate::FolderCompare::broken_symlinks) instead of being
+/// skipped silently. This applies to links found in the second directory as well.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Symlinks {
+    /// Symbolic links are skipped entirely. This is the default.
+    #[default]
+    Skip,
+    /// Symbolic links to directories are followed and their content is compared as if it was
+    /// part of the tree, while symbolic links to files are still skipped.
+    FollowDirectories,
+    /// All symbolic links are followed. Links to files are compared by the content they point to,
+    /// so a link in one directory matches a regular file with the same content in the other one.
+    Follow,
+    /// Symbolic links aren't followed, but compared like files by the path they point to as
+    /// returned by `read_link`, without reading the target. Two links are unchanged if their
+    /// target paths are exactly the same, even if the targets don't exist, and a link is changed
+    /// compared to anything that isn't a link. This suits trees of alternatives like `/etc`.
+    CompareTargets,
+}
+
+/// Handling of entries which can't be read while walking, see [`CompareOptions::walk_errors`].
+/// Broken symbolic links aren't errors, they are listed on their own.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum WalkErrors {
+    /// The entry, and for a directory everything below it, is left out silently.
+    Skip,
+    /// The entry is listed in [`FolderCompare::errors`](crate::FolderCompare::errors) and the
+    /// comparison goes on without it. This is the default.
+    #[default]
+    Record,
+    /// The comparison fails with the error.
+    Abort,
+}
+
+/// The order of the lists of the result, see [`CompareOptions::order_by`]. Files whose metadata
+/// can't be read are sorted as empty and as modified at the Unix epoch.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OrderBy {
+    /// The files stay in the order they were found in. This is the default.
+    #[default]
+    Walk,
+    /// The files are sorted by path.
+    PathAsc,
+    /// The files are sorted by size, largest first.
+    SizeDesc,
+    /// The files are sorted by modification time, most recent first.
+    MtimeDesc,
+}
+
+/// How files existing in both directories are compared, see [`CompareOptions::mode`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CompareMode {
+    /// Files are compared by hashing their content. This is the default.
+    ///
+    /// Two empty files are always unchanged and an empty file is always changed compared to a
+    /// file with content. Empty files are read like all other files though, so files whose
+    /// metadata reports a size of zero while they still have content are compared by that content.
+    /// Files of other, different sizes are changed without reading either of them, unless
+    /// [`CompareOptions::ignore_line_pattern`] or [`CompareOptions::ignore_trailing_nulls`] lets
+    /// content of different sizes match. These files aren't hashed and therefore missing from
+    /// [`FolderCompare::changed_hashes`].
+    ///
+    /// [`FolderCompare::changed_hashes`]: crate::FolderCompare::changed_hashes
+    #[default]
+    Content,
+    /// Files are compared by their size only, no file is read. This is a lot faster, but misses
+    /// changes keeping the size, and [`FolderCompare::changed_hashes`] stays empty.
+    ///
+    /// [`FolderCompare::changed_hashes`]: crate::FolderCompare::changed_hashes
+    Size,
+    /// Files are changed only if the one in the first directory was modified later than its
+    /// counterpart, by at least [`CompareOptions::mtime_granularity`], like `rsync --update`
+    /// decides which files to transfer. No file is read, so files with different content whose
+    /// counterpart is as old or newer are unchanged. This is meant for one-way incremental syncs
+    /// from the first directory to the second one. As it replaces hashing, it excludes comparing
+    /// content: Options tuning how content is hashed, like [`CompareOptions::ignore_case`] or
+    /// [`CompareOptions::sample_offsets`], have no effect and
+    /// [`FolderCompare::changed_hashes`] stays empty.
+    ///
+    /// [`FolderCompare::changed_hashes`]: crate::FolderCompare::changed_hashes
+    Newer,
+}
+
+/// The kinds of entries taken into account by the comparison.
+///
+/// Directories are classified by their presence only: A directory missing in the second directory
+/// is listed in [`FolderCompare::new_files`], one existing in both in
+/// [`FolderCompare::unchanged_files`] and one missing in the first directory in
+/// [`FolderCompare::deleted_files`]. This also makes empty directories show up.
+///
+/// [`FolderCompare::new_files`]: crate::FolderCompare::new_files
+/// [`FolderCompare::unchanged_files`]: crate::FolderCompare::unchanged_files
+/// [`FolderCompare::deleted_files`]: crate::FolderCompare::deleted_files
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKinds {
+    /// Only files are compared. This is the default.
+    #[default]
+    Files,
+    /// Only directories are compared.
+    Dirs,
+    /// Both files and directories are compared.
+    Both,
+}
+
+/// A hook overriding the classification of entries, see [`CompareOptions::reclassify`].
+///
+/// # Example
+///
+/// The following hook treats every changed `.lock` file as unchanged
+///
+///```
+/// use folder_compare::{CompareOptions, FileStatus, Reclassify};
+///
+/// let options = CompareOptions {
+///     reclassify: Some(Reclassify::new(|path, status| {
+///         match (status, path.extension()) {
+///             (FileStatus::Changed, Some(extension)) if extension == "lock" => FileStatus::Unchanged,
+///             _ => status,
+///         }
+///     })),
+///     ..Default::default()
+/// };
+///```
+///
+#[derive(Clone)]
+pub struct Reclassify(Arc<ReclassifyFn>);
+
+type ReclassifyFn = dyn Fn(&Path, FileStatus) -> FileStatus + Send + Sync;
+
+impl Reclassify {
+    pub fn new<F>(reclassify: F) -> Self
+    where
+        F: Fn(&Path, FileStatus) -> FileStatus + Send + Sync + 'static,
+    {
+        Reclassify(Arc::new(reclassify))
+    }
+
+    pub(crate) fn call(&self, path: &Path, status: FileStatus) -> FileStatus {
+        (self.0)(path, status)
+    }
+}
+
+impl fmt::Debug for Reclassify {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Reclassify")
+    }
+}
+/// A closure configuring the walks over both directories, see [`CompareOptions::configure_walk`].
+///
+/// # Example
+///
+/// The following closure keeps the walks on the file system the directories are on and out of
+/// subdirectories deeper than three levels
+///
+///```
+/// use folder_compare::{CompareOptions, ConfigureWalk};
+///
+/// let options = CompareOptions {
+///     configure_walk: Some(ConfigureWalk::new(|walk| walk.same_file_system(true).max_depth(3))),
+///     ..Default::default()
+/// };
+///```
+///
+#[derive(Clone)]
+pub struct ConfigureWalk(Arc<ConfigureWalkFn>);
+
+type ConfigureWalkFn = dyn Fn(WalkDir) -> WalkDir + Send + Sync;
+
+impl ConfigureWalk {
+    pub fn new<F>(configure: F) -> Self
+    where
+        F: Fn(WalkDir) -> WalkDir + Send + Sync + 'static,
+    {
+        ConfigureWalk(Arc::new(configure))
+    }
+
+    pub(crate) fn call(&self, walk: WalkDir) -> WalkDir {
+        (self.0)(walk)
+    }
+}
+
+impl fmt::Debug for ConfigureWalk {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("ConfigureWalk")
+    }
+}
+
+/// The order in which the entries of a directory are visited, see [`CompareOptions::order`].
+///
+/// # Example
+///
+/// The following order visits the largest files first
+///
+///```
+/// use folder_compare::{CompareOptions, EntryOrder};
+///
+/// let options = CompareOptions {
+///     order: Some(EntryOrder::new(|a, b| {
+///         let size = |path: &std::path::Path| path.metadata().map(|m| m.len()).unwrap_or(0);
+///         size(b).cmp(&size(a))
+///     })),
+///     ..Default::default()
+/// };
+///```
+///
+#[derive(Clone)]
+pub struct EntryOrder(Arc<EntryOrderFn>);
+
+type EntryOrderFn = dyn Fn(&Path, &Path) -> Ordering + Send + Sync;
+
+impl EntryOrder {
+    /// Orders entries by the comparator `order`, which is passed the paths of two entries sharing
+    /// the same parent.
+    pub fn new<F>(order: F) -> Self
+    where
+        F: Fn(&Path, &Path) -> Ordering + Send + Sync + 'static,
+    {
+        EntryOrder(Arc::new(order))
+    }
+
+    /// Orders entries by their file names.
+    pub fn by_file_name() -> Self {
+        EntryOrder::new(|a, b| a.file_name().cmp(&b.file_name()))
+    }
+
+    pub(crate) fn call(&self, a: &Path, b: &Path) -> Ordering {
+        (self.0)(a, b)
+    }
+}
+
+impl fmt::Debug for EntryOrder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("EntryOrder")
+    }
+}
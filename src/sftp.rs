@@ -0,0 +1,83 @@
+use regex::RegexSet;
+use ssh2::Session;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::hash::ContentHasher;
+use crate::walk::files_to_compare;
+use crate::{CompareOptions, Error, FileError, FolderCompare};
+
+/// Compares the local directory at `local` with the directory at `remote_base` on the server
+/// `session` is connected to, without mounting it. The local directory is walked and the content
+/// of every file is compared with the remote file at the same relative path, which is fetched over
+/// SFTP. Everything matching the `excluded` patterns is left out.
+///
+/// Files missing on the server are listed as new. Files that can't be fetched, e.g. because the
+/// connection broke down, are listed in [`FolderCompare::errors`] and the comparison goes on. Only
+/// errors setting up the SFTP subsystem fail the whole comparison.
+///
+/// Available with the `ssh` feature.
+///
+/// # Example
+///
+///```no_run
+/// use std::net::TcpStream;
+/// use std::path::Path;
+/// use folder_compare::compare_with_sftp;
+///
+/// let mut session = ssh2::Session::new().unwrap();
+/// session.set_tcp_stream(TcpStream::connect("example.com:22").unwrap());
+/// session.handshake().unwrap();
+/// session.userauth_agent("deploy").unwrap();
+///
+/// let result = compare_with_sftp(Path::new("/srv/site"), &session, Path::new("/var/www/site"), &vec![]).unwrap();
+///```
+///
+pub fn compare_with_sftp(local: &Path, session: &Session, remote_base: &Path, excluded: &Vec<String>) -> Result<FolderCompare, Error> {
+    let set = RegexSet::new(excluded)?;
+    let options = CompareOptions::default();
+    let sftp = session.sftp()?;
+
+    let mut hasher = ContentHasher::new();
+    let mut result = FolderCompare {
+        path1: local.to_path_buf(),
+        path2: remote_base.to_path_buf(),
+        ..Default::default()
+    };
+
+    for entry in files_to_compare(local, &set, &options) {
+        let remote = remote_path(remote_base, entry.path().strip_prefix(local)?);
+        let remote_hash = match sftp.open(&remote).map_err(io::Error::from).and_then(|file| hasher.hash_reader(file, &mut |_| {})) {
+            Ok(hash) => hash,
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => {
+                result.new_files.push(entry.into_path());
+                continue;
+            }
+            Err(error) => {
+                result.errors.push(FileError::new(&remote, &error));
+                continue;
+            }
+        };
+
+        let local_hash = hasher.hash_file(entry.path(), &mut |_| {})?;
+        if local_hash == remote_hash {
+            result.unchanged_files.push(entry.into_path());
+        } else {
+            result.changed_hashes.push((entry.path().to_path_buf(), local_hash, remote_hash));
+            result.changed_files.push(entry.into_path());
+        }
+    }
+
+    Ok(result)
+}
+
+/// Forms the path of the counterpart of the local file at the `relative` path on the server,
+/// always separating components with `/`.
+fn remote_path(remote_base: &Path, relative: &Path) -> PathBuf {
+    let mut remote = remote_base.to_string_lossy().trim_end_matches('/').to_string();
+    for component in relative.iter() {
+        remote.push('/');
+        remote.push_str(&component.to_string_lossy());
+    }
+    PathBuf::from(remote)
+}
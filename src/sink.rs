@@ -0,0 +1,144 @@
+use std::path::Path;
+
+use crate::{FileError, FileStatus, FolderCompare};
+
+/// Receives the entries of a comparison as soon as they are classified, see
+/// [`Comparator::compare_into`](crate::Comparator::compare_into).
+///
+/// All methods do nothing by default, so only the ones of interest need to be implemented.
+/// [`FolderCompare`] is a sink collecting the entries into its lists.
+///
+/// # Example
+///
+/// The following sink counts the changed files
+///
+///```no_run
+/// use std::path::Path;
+/// use folder_compare::{Comparator, CompareOptions, ResultSink};
+///
+/// struct CountChanged(usize);
+///
+/// impl ResultSink for CountChanged {
+///     fn on_changed(&mut self, _path: &Path) {
+///         self.0 += 1;
+///     }
+/// }
+///
+/// let mut sink = CountChanged(0);
+/// let mut comparator = Comparator::new(&vec![], CompareOptions::default()).unwrap();
+/// comparator.compare_into(Path::new("/tmp/a"), Path::new("/tmp/b"), &mut sink).unwrap();
+///```
+///
+pub trait ResultSink {
+    /// Called with the path of a new entry, only existing in the first directory.
+    fn on_new(&mut self, _path: &Path) {}
+
+    /// Called with the path of an entry existing in both directories with different content.
+    fn on_changed(&mut self, _path: &Path) {}
+
+    /// Called with the path of an entry existing in both directories with the same content.
+    fn on_unchanged(&mut self, _path: &Path) {}
+
+    /// Called with the path of a deleted entry, only existing in the second directory.
+    fn on_deleted(&mut self, _path: &Path) {}
+
+    /// Called with the path of a file whose counterpart is a truncated copy and the number of bytes
+    /// missing from it, see [`CompareOptions::detect_partial`](crate::CompareOptions::detect_partial).
+    fn on_partial(&mut self, _path: &Path, _missing: u64) {}
+
+    /// Called with a file that couldn't be read, see
+    /// [`CompareOptions::continue_on_error`](crate::CompareOptions::continue_on_error).
+    fn on_error(&mut self, _error: &FileError) {}
+
+    /// Called with an entry that couldn't be read while walking the directories, see
+    /// [`CompareOptions::walk_errors`](crate::CompareOptions::walk_errors). Passes it on to
+    /// [`on_error`](ResultSink::on_error) by default.
+    fn on_walk_error(&mut self, error: &FileError) {
+        self.on_error(error);
+    }
+}
+
+impl ResultSink for FolderCompare {
+    fn on_new(&mut self, path: &Path) {
+        self.new_files.push(path.to_path_buf());
+    }
+
+    fn on_changed(&mut self, path: &Path) {
+        self.changed_files.push(path.to_path_buf());
+    }
+
+    fn on_unchanged(&mut self, path: &Path) {
+        self.unchanged_files.push(path.to_path_buf());
+    }
+
+    fn on_deleted(&mut self, path: &Path) {
+        self.deleted_files.push(path.to_path_buf());
+    }
+
+    fn on_partial(&mut self, path: &Path, missing: u64) {
+        self.partial.push((path.to_path_buf(), missing));
+    }
+
+    fn on_error(&mut self, error: &FileError) {
+        self.errors.push(error.clone());
+    }
+}
+
+/// A sink ignoring all entries.
+pub(crate) struct NoSink;
+
+impl ResultSink for NoSink {}
+
+/// A sink counting the classified entries and files that couldn't be read, calling `progress`
+/// with the count so far and `total` for every one of them. Entries the walks couldn't read aren't
+/// part of the total, so they aren't counted either.
+pub(crate) struct FileProgress<F> {
+    pub(crate) done: u64,
+    pub(crate) total: u64,
+    pub(crate) progress: F,
+}
+
+impl<F: FnMut(u64, u64)> FileProgress<F> {
+    fn step(&mut self) {
+        self.done += 1;
+        (self.progress)(self.done, self.total);
+    }
+}
+
+impl<F: FnMut(u64, u64)> ResultSink for FileProgress<F> {
+    fn on_new(&mut self, _path: &Path) {
+        self.step();
+    }
+
+    fn on_changed(&mut self, _path: &Path) {
+        self.step();
+    }
+
+    fn on_unchanged(&mut self, _path: &Path) {
+        self.step();
+    }
+
+    fn on_deleted(&mut self, _path: &Path) {
+        self.step();
+    }
+
+    fn on_partial(&mut self, _path: &Path, _missing: u64) {
+        self.step();
+    }
+
+    fn on_error(&mut self, _error: &FileError) {
+        self.step();
+    }
+
+    fn on_walk_error(&mut self, _error: &FileError) {}
+}
+
+/// Passes the entry at `path` to the method of `sink` matching its `status`.
+pub(crate) fn notify(sink: &mut dyn ResultSink, status: FileStatus, path: &Path) {
+    match status {
+        FileStatus::New => sink.on_new(path),
+        FileStatus::Changed => sink.on_changed(path),
+        FileStatus::Unchanged => sink.on_unchanged(path),
+        FileStatus::Deleted => sink.on_deleted(path),
+    }
+}
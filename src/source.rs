@@ -0,0 +1,136 @@
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use walkdir::WalkDir;
+
+use crate::Error;
+
+/// A tree of files which can be compared with [`FolderCompare::from_sources`] without being a
+/// directory on disk, e.g. the entries of an archive.
+///
+/// All paths handled by a source are relative to its root.
+///
+/// Sources which are expensive to read, like a bucket or a server, should implement
+/// [`metadata`](FileSource::metadata) from what they learn while listing their files. Content is
+/// then only fetched for files which have the same size on both sides but no matching
+/// modification time.
+///
+/// # Example
+///
+/// A source backed by a remote index which lists sizes and modification times along with the
+/// paths, so that only files which may have changed are downloaded:
+///
+///```
+/// use std::collections::HashMap;
+/// use std::io::Read;
+/// use std::path::{Path, PathBuf};
+/// use folder_compare::{DirSource, Error, FileMetadata, FileSource, FolderCompare};
+///
+/// struct RemoteSource {
+///     index: HashMap<PathBuf, FileMetadata>,
+///     downloads: usize,
+/// }
+///
+/// impl RemoteSource {
+///     fn download(&self, path: &Path) -> Result<Vec<u8>, Error> {
+///         // e.g. a GET request for the object at `path`
+///         Ok(b"Test".to_vec())
+///     }
+/// }
+///
+/// impl FileSource for RemoteSource {
+///     fn files(&mut self) -> Result<Vec<PathBuf>, Error> {
+///         Ok(self.index.keys().cloned().collect())
+///     }
+///
+///     fn metadata(&mut self, path: &Path) -> Result<Option<FileMetadata>, Error> {
+///         Ok(self.index.get(path).copied())
+///     }
+///
+///     fn open(&mut self, path: &Path) -> Result<Option<Box<dyn Read + '_>>, Error> {
+///         if !self.index.contains_key(path) {
+///             return Ok(None);
+///         }
+///         self.downloads += 1;
+///         Ok(Some(Box::new(std::io::Cursor::new(self.download(path)?))))
+///     }
+/// }
+///
+/// let mut local = DirSource::new(Path::new("/tmp/site"));
+/// let mut remote = RemoteSource {
+///     index: HashMap::from([(PathBuf::from("index.html"), FileMetadata { size: 4, modified: None })]),
+///     downloads: 0,
+/// };
+/// let result = FolderCompare::from_sources(&mut local, &mut remote, &vec![]).unwrap();
+///```
+///
+/// [`FolderCompare::from_sources`]: crate::FolderCompare::from_sources
+pub trait FileSource {
+    /// Lists the relative paths of all files in the source.
+    fn files(&mut self) -> Result<Vec<PathBuf>, Error>;
+
+    /// Returns the size and modification time of the file at the relative `path` if the source
+    /// knows them without reading the file, `None` otherwise. The default knows nothing.
+    fn metadata(&mut self, _path: &Path) -> Result<Option<FileMetadata>, Error> {
+        Ok(None)
+    }
+
+    /// Opens the file at the relative `path` for reading, or returns `None` if the source has no
+    /// such file.
+    fn open(&mut self, path: &Path) -> Result<Option<Box<dyn Read + '_>>, Error>;
+}
+
+/// The metadata of a file in a [`FileSource`] which is cheap to get.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileMetadata {
+    /// The size of the file in bytes.
+    pub size: u64,
+    /// The modification time of the file, if the source records one.
+    pub modified: Option<SystemTime>,
+}
+
+/// A [`FileSource`] backed by a directory on disk. Symbolic links are skipped just like
+/// [`FolderCompare::new`] does.
+///
+/// [`FolderCompare::new`]: crate::FolderCompare::new
+#[derive(Debug, Clone)]
+pub struct DirSource {
+    root: PathBuf,
+}
+
+impl DirSource {
+    pub fn new(root: &Path) -> Self {
+        DirSource { root: root.to_path_buf() }
+    }
+}
+
+impl FileSource for DirSource {
+    fn files(&mut self) -> Result<Vec<PathBuf>, Error> {
+        let mut files = vec![];
+        for entry in WalkDir::new(&self.root).into_iter().filter_map(|e| e.ok()) {
+            if entry.file_type().is_file() {
+                files.push(entry.path().strip_prefix(&self.root)?.to_path_buf());
+            }
+        }
+        Ok(files)
+    }
+
+    fn metadata(&mut self, path: &Path) -> Result<Option<FileMetadata>, Error> {
+        match fs::metadata(self.root.join(path)) {
+            Ok(metadata) if metadata.is_file() => Ok(Some(FileMetadata {
+                size: metadata.len(),
+                modified: metadata.modified().ok(),
+            })),
+            _ => Ok(None),
+        }
+    }
+
+    fn open(&mut self, path: &Path) -> Result<Option<Box<dyn Read + '_>>, Error> {
+        let full_path = self.root.join(path);
+        if !full_path.is_file() {
+            return Ok(None);
+        }
+        Ok(Some(Box::new(File::open(full_path)?)))
+    }
+}
@@ -0,0 +1,33 @@
+use std::fs::File;
+use std::io;
+
+/// Finds the first region of data at or after `offset` in `file` and returns its start and end,
+/// or `None` if only holes follow. Where holes can't be found, because of the platform or the file
+/// system, the whole rest of the file is taken as data.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn data_region(file: &File, offset: u64) -> io::Result<Option<(u64, u64)>> {
+    use std::os::unix::io::AsRawFd;
+
+    let fd = file.as_raw_fd();
+    // Only moves the position of `file`, which is set again before reading.
+    let start = unsafe { libc::lseek(fd, offset as libc::off_t, libc::SEEK_DATA) };
+    if start < 0 {
+        let error = io::Error::last_os_error();
+        return match error.raw_os_error() {
+            Some(libc::ENXIO) => Ok(None),
+            Some(libc::EINVAL) | Some(libc::EOPNOTSUPP) => Ok(Some((offset, u64::MAX))),
+            _ => Err(error),
+        };
+    }
+    let end = unsafe { libc::lseek(fd, start, libc::SEEK_HOLE) };
+    if end < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(Some((start as u64, end as u64)))
+}
+
+/// Takes the whole rest of the file as data where holes can't be found.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub(crate) fn data_region(_file: &File, offset: u64) -> io::Result<Option<(u64, u64)>> {
+    Ok(Some((offset, u64::MAX)))
+}
@@ -0,0 +1,99 @@
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+use crate::FileStatus;
+
+/// The result of a comparison shaped as a directory tree, see [`FolderCompare::diff_tree`].
+///
+/// Every node carries a status. Files keep the status they were classified with. Directories get
+/// a status aggregated from their children: [`FileStatus::New`] or [`FileStatus::Deleted`] if all
+/// children are, [`FileStatus::Unchanged`] if all children are unchanged and
+/// [`FileStatus::Changed`] otherwise. So a directory is changed as soon as anything within it
+/// differs. Directories without children, e.g. ones listed with [`EntryKinds::Dirs`], keep their
+/// own status.
+///
+/// [`FolderCompare::diff_tree`]: crate::FolderCompare::diff_tree
+/// [`EntryKinds::Dirs`]: crate::EntryKinds::Dirs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffTree {
+    /// The file name of the entry, empty for the root of the tree.
+    pub name: OsString,
+    /// The status of the entry, aggregated from its children for directories.
+    pub status: FileStatus,
+    /// The entries within this directory, sorted by name. Empty for files.
+    pub children: Vec<DiffTree>,
+}
+
+/// A node of a [`DiffTree`] under construction.
+#[derive(Default)]
+struct Node {
+    status: Option<FileStatus>,
+    children: BTreeMap<OsString, Node>,
+}
+
+impl DiffTree {
+    /// Builds the tree from the status of every entry keyed by relative path, as returned by
+    /// [`FolderCompare::statuses`](crate::FolderCompare::statuses).
+    pub(crate) fn from_statuses(statuses: HashMap<PathBuf, FileStatus>) -> Self {
+        let mut root = Node::default();
+        for (path, status) in statuses {
+            let mut node = &mut root;
+            for component in path.iter() {
+                node = node.children.entry(component.to_os_string()).or_default();
+            }
+            node.status = Some(status);
+        }
+        DiffTree::from_node(OsString::new(), root)
+    }
+
+    fn from_node(name: OsString, node: Node) -> Self {
+        let children: Vec<DiffTree> = node.children.into_iter().map(|(name, child)| DiffTree::from_node(name, child)).collect();
+        let status = match children.split_first() {
+            None => node.status.unwrap_or(FileStatus::Unchanged),
+            Some((first, rest)) => match first.status {
+                FileStatus::New | FileStatus::Deleted | FileStatus::Unchanged if rest.iter().all(|child| child.status == first.status) => first.status,
+                _ => FileStatus::Changed,
+            },
+        };
+        DiffTree { name, status, children }
+    }
+}
+
+/// The number of entries of each status within a directory and all its subdirectories, see
+/// [`FolderCompare::dir_stats`](crate::FolderCompare::dir_stats).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DirStats {
+    /// The number of new entries.
+    pub new: usize,
+    /// The number of changed entries.
+    pub changed: usize,
+    /// The number of unchanged entries.
+    pub unchanged: usize,
+    /// The number of deleted entries.
+    pub deleted: usize,
+}
+
+impl DirStats {
+    /// Counts the entries within every directory, keyed by relative path, from the status of every
+    /// entry keyed by relative path. Each entry counts for all directories above it, up to the root
+    /// keyed by an empty path.
+    pub(crate) fn from_statuses(statuses: &HashMap<PathBuf, FileStatus>) -> HashMap<PathBuf, DirStats> {
+        let mut stats: HashMap<PathBuf, DirStats> = HashMap::new();
+        for (path, status) in statuses {
+            for dir in path.ancestors().skip(1) {
+                stats.entry(dir.to_path_buf()).or_default().count(*status);
+            }
+        }
+        stats
+    }
+
+    fn count(&mut self, status: FileStatus) {
+        match status {
+            FileStatus::New => self.new += 1,
+            FileStatus::Changed => self.changed += 1,
+            FileStatus::Unchanged => self.unchanged += 1,
+            FileStatus::Deleted => self.deleted += 1,
+        }
+    }
+}
@@ -0,0 +1,300 @@
+use fxhash::FxHasher;
+use regex::RegexSet;
+use std::hash::Hasher;
+use std::ffi::OsStr;
+use std::fs::{self, FileType, Metadata};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use walkdir::WalkDir;
+
+use crate::{CompareOptions, EntryKinds, Symlinks};
+
+/// Counts the entries left out while walking, shared by all walks of a comparison, see
+/// [`FolderCompare::excluded_files_count`](crate::FolderCompare::excluded_files_count).
+#[derive(Debug, Default)]
+pub(crate) struct WalkCounts {
+    pub(crate) excluded_files: AtomicUsize,
+    pub(crate) pruned_dirs: AtomicUsize,
+    pub(crate) skipped_symlinks: AtomicUsize,
+}
+
+/// An entry found while walking one of the directories.
+pub(crate) struct Entry {
+    path: PathBuf,
+    file_type: FileType,
+    is_symlink: bool,
+}
+
+impl Entry {
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(crate) fn into_path(self) -> PathBuf {
+        self.path
+    }
+
+    /// The type of the entry, which is the type of the target for symbolic links followed.
+    pub(crate) fn file_type(&self) -> FileType {
+        self.file_type
+    }
+
+    /// Reads the metadata of the entry, following symbolic links.
+    pub(crate) fn metadata(&self) -> io::Result<Metadata> {
+        fs::metadata(&self.path)
+    }
+}
+
+impl From<walkdir::DirEntry> for Entry {
+    fn from(entry: walkdir::DirEntry) -> Self {
+        Entry {
+            file_type: entry.file_type(),
+            is_symlink: entry.path_is_symlink(),
+            path: entry.into_path(),
+        }
+    }
+}
+
+/// An entry that couldn't be read while walking one of the directories.
+pub(crate) struct WalkError {
+    path: Option<PathBuf>,
+    error: io::Error,
+}
+
+impl WalkError {
+    /// The path of the entry, if known.
+    pub(crate) fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub(crate) fn error(&self) -> &io::Error {
+        &self.error
+    }
+}
+
+impl From<walkdir::Error> for WalkError {
+    fn from(error: walkdir::Error) -> Self {
+        WalkError {
+            path: error.path().map(Path::to_path_buf),
+            error: error.into(),
+        }
+    }
+}
+
+impl From<WalkError> for io::Error {
+    fn from(error: WalkError) -> Self {
+        error.error
+    }
+}
+
+/// Walks `root` and yields all entries that take part in the comparison, leaving out symbolic links
+/// depending on the options and everything matching the excluded patterns. Which entries are
+/// yielded depends on [`CompareOptions::entry_kinds`]. With [`CompareOptions::strict_regular`]
+/// special files like FIFOs or sockets are yielded as well. Entries which couldn't be read are
+/// yielded as errors.
+pub(crate) fn entries_to_compare<'a>(root: &Path, set: &'a RegexSet, options: &'a CompareOptions) -> impl Iterator<Item = Result<Entry, WalkError>> + 'a {
+    counted_entries(root, set, options, Arc::default())
+}
+
+/// Like [`entries_to_compare`], but adds the files left out as excluded, the directories pruned
+/// and the symbolic links not followed to `counts`.
+pub(crate) fn counted_entries<'a>(root: &Path, set: &'a RegexSet, options: &'a CompareOptions, counts: Arc<WalkCounts>) -> impl Iterator<Item = Result<Entry, WalkError>> + 'a {
+    let files = options.entry_kinds != EntryKinds::Dirs;
+    let dirs = options.entry_kinds != EntryKinds::Files;
+    let root_path = root.to_path_buf();
+    walk(root, options, counts.clone())
+        .filter(move |entry| match entry {
+            Ok(entry) => {
+                let file_type = entry.file_type();
+                if !file_type.is_dir() && is_excluded(entry.path(), set) {
+                    counts.excluded_files.fetch_add(1, Ordering::Relaxed);
+                    return false;
+                }
+                if entry.is_symlink && !file_type.is_dir() && !matches!(options.symlinks, Symlinks::Follow | Symlinks::CompareTargets) {
+                    counts.skipped_symlinks.fetch_add(1, Ordering::Relaxed);
+                    return false;
+                }
+                ((files && file_type.is_file() && size_matches(entry, options))
+                    || (dirs && file_type.is_dir())
+                    || (files && options.strict_regular && is_special_type(file_type))
+                    || (files && options.symlinks == Symlinks::CompareTargets && file_type.is_symlink()))
+                    && (!entry.is_symlink || matches!(options.symlinks, Symlinks::Follow | Symlinks::CompareTargets))
+                    && (!file_type.is_dir() || !is_excluded(entry.path(), set))
+                    && in_shard(&root_path, entry.path(), options)
+            }
+            Err(error) => !error.path().is_some_and(|path| is_excluded(path, set)),
+        })
+}
+
+/// Like [`entries_to_compare`], but silently skips entries which couldn't be read.
+pub(crate) fn files_to_compare<'a>(root: &Path, set: &'a RegexSet, options: &'a CompareOptions) -> impl Iterator<Item = Entry> + 'a {
+    entries_to_compare(root, set, options).filter_map(|entry| entry.ok())
+}
+
+/// Walks `root` and yields the symbolic links [`entries_to_compare`] leaves out because they
+/// aren't followed, see [`CompareOptions::symlinks`]. Links to directories followed with
+/// [`Symlinks::FollowDirectories`] aren't yielded, their content is compared.
+pub(crate) fn skipped_symlinks<'a>(root: &Path, set: &'a RegexSet, options: &'a CompareOptions) -> impl Iterator<Item = PathBuf> + 'a {
+    walk(root, options, Arc::default())
+        .filter_map(|entry| entry.ok())
+        .filter(move |entry| {
+            entry.is_symlink
+                && matches!(options.symlinks, Symlinks::Skip | Symlinks::FollowDirectories)
+                && !entry.file_type().is_dir()
+                && !is_excluded(entry.path(), set)
+        })
+        .map(Entry::into_path)
+}
+
+/// Walks all entries below `root` set up according to `options`, adding the directories pruned to
+/// `counts`. With [`CompareOptions::subpaths`] only the subdirectories existing in `root` are
+/// walked, one after the other, sorted by their paths with [`CompareOptions::merge_join`].
+/// Subdirectories listed twice or lying within another one are walked only once.
+fn walk(root: &Path, options: &CompareOptions, counts: Arc<WalkCounts>) -> Box<dyn Iterator<Item = Result<Entry, WalkError>>> {
+    if options.subpaths.is_empty() {
+        return walk_dir(root, options, counts);
+    }
+    let existing: Vec<PathBuf> = options.subpaths.iter().map(|subpath| root.join(subpath)).filter(|dir| dir.is_dir()).collect();
+    let mut dirs: Vec<PathBuf> = vec![];
+    for dir in &existing {
+        let nested = existing.iter().any(|other| other != dir && dir.starts_with(other));
+        if !nested && !dirs.contains(dir) {
+            dirs.push(dir.clone());
+        }
+    }
+    if options.merge_join {
+        dirs.sort();
+    }
+    let walks: Vec<_> = dirs.iter().map(|dir| walk_dir(dir, options, counts.clone())).collect();
+    Box::new(walks.into_iter().flatten())
+}
+
+/// Walks all entries below `root` set up according to `options`, see [`walk`].
+fn walk_dir(root: &Path, options: &CompareOptions, counts: Arc<WalkCounts>) -> Box<dyn Iterator<Item = Result<Entry, WalkError>>> {
+    #[cfg(feature = "parallel-walk")]
+    {
+        if options.parallel_walk {
+            return parallel_walk(root, options, counts);
+        }
+    }
+
+    let mut walker = WalkDir::new(root)
+        .min_depth(1)
+        .follow_links(follows_links(options))
+        .same_file_system(options.same_file_system);
+    if let Some(ref configure_walk) = options.configure_walk {
+        walker = configure_walk.call(walker);
+    }
+    if options.merge_join {
+        walker = walker.sort_by_file_name();
+    } else if let Some(ref order) = options.order {
+        let order = order.clone();
+        walker = walker.sort_by(move |a, b| order.call(a.path(), b.path()));
+    }
+    let prune = options.prune_dir_names.clone();
+    Box::new(walker.into_iter()
+        .filter_entry(move |entry| !(entry.file_type().is_dir() && is_pruned(entry.file_name(), &prune, &counts)))
+        .map(|entry| entry.map(Entry::from).map_err(WalkError::from)))
+}
+
+/// Walks all entries below `root` with `jwalk`, reading directories on several threads, see
+/// [`CompareOptions::parallel_walk`].
+#[cfg(feature = "parallel-walk")]
+fn parallel_walk(root: &Path, options: &CompareOptions, counts: Arc<WalkCounts>) -> Box<dyn Iterator<Item = Result<Entry, WalkError>>> {
+    use std::cmp::Ordering;
+
+    let mut walker = jwalk::WalkDir::new(root)
+        .skip_hidden(false)
+        .min_depth(1)
+        .follow_links(follows_links(options))
+        .sort(options.merge_join);
+    let order = if options.merge_join { None } else { options.order.clone() };
+    let prune = options.prune_dir_names.clone();
+    walker = walker.process_read_dir(move |_, _, _, children| {
+        children.retain(|child| match child {
+            Ok(child) => !(child.file_type().is_dir() && is_pruned(&child.file_name, &prune, &counts)),
+            Err(_) => true,
+        });
+        if let Some(ref order) = order {
+            children.sort_by(|a, b| match (a, b) {
+                (Ok(a), Ok(b)) => order.call(&a.path(), &b.path()),
+                (Ok(_), Err(_)) => Ordering::Less,
+                (Err(_), Ok(_)) => Ordering::Greater,
+                (Err(_), Err(_)) => Ordering::Equal,
+            })
+        }
+    });
+    Box::new(walker.into_iter().map(|entry| match entry {
+        Ok(entry) => Ok(Entry {
+            file_type: entry.file_type(),
+            is_symlink: entry.path_is_symlink(),
+            path: entry.path(),
+        }),
+        Err(error) => Err(WalkError {
+            path: error.path().map(Path::to_path_buf),
+            error: error.into(),
+        }),
+    }))
+}
+
+/// Checks whether a directory named `name` is left out with [`CompareOptions::prune_dir_names`],
+/// counting it in `counts` if so.
+fn is_pruned(name: &OsStr, prune: &[String], counts: &WalkCounts) -> bool {
+    let pruned = prune.iter().any(|pruned| OsStr::new(pruned) == name);
+    if pruned {
+        counts.pruned_dirs.fetch_add(1, Ordering::Relaxed);
+    }
+    pruned
+}
+
+/// Checks whether the walks follow symbolic links, see [`CompareOptions::symlinks`].
+fn follows_links(options: &CompareOptions) -> bool {
+    matches!(options.symlinks, Symlinks::FollowDirectories | Symlinks::Follow)
+}
+
+/// Checks whether `path` matches one of the excluded patterns. Paths that aren't valid UTF-8 are
+/// matched with invalid sequences replaced by U+FFFD.
+pub(crate) fn is_excluded(path: &Path, set: &RegexSet) -> bool {
+    set.matches(&path.to_string_lossy()).matched_any()
+}
+
+/// Checks whether the entry at `path` below `root` belongs to the shard set with
+/// [`CompareOptions::shard`], going by a hash of its relative path. The shard is expected to be
+/// valid, as checked by [`Comparator::new`](crate::Comparator::new).
+pub(crate) fn in_shard(root: &Path, path: &Path, options: &CompareOptions) -> bool {
+    let (index, total) = match options.shard {
+        None => return true,
+        Some(shard) => shard,
+    };
+    let mut hasher = FxHasher::default();
+    hasher.write(path.strip_prefix(root).unwrap_or(path).to_string_lossy().as_bytes());
+    hasher.finish() % total as u64 == index as u64
+}
+
+/// Checks whether the size of the file `entry` is within [`CompareOptions::size_filter`].
+fn size_matches(entry: &Entry, options: &CompareOptions) -> bool {
+    match options.size_filter {
+        None => true,
+        Some(ref range) => entry.metadata().map(|m| range.contains(&m.len())).unwrap_or(false),
+    }
+}
+
+/// Checks whether `file_type` is neither a regular file, nor a directory, nor a symbolic link.
+pub(crate) fn is_special_type(file_type: FileType) -> bool {
+    !file_type.is_file() && !file_type.is_dir() && !file_type.is_symlink()
+}
+
+/// Forms the path of the counterpart of the file at the `relative` path in the directory `root`.
+/// With [`CompareOptions::normalize_separators`] both `/` and `\` separate path components.
+pub(crate) fn counterpart_path(root: &Path, relative: &Path, options: &CompareOptions) -> PathBuf {
+    match relative.to_str() {
+        Some(relative) if options.normalize_separators => {
+            let mut path = root.to_path_buf();
+            relative.split(['/', '\\']).filter(|part| !part.is_empty()).for_each(|part| path.push(part));
+            path
+        }
+        _ => root.join(relative),
+    }
+}
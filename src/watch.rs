@@ -0,0 +1,140 @@
+use notify::{EventKind, RecursiveMode, Watcher};
+use regex::RegexSet;
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::time::Duration;
+
+use crate::walk::{files_to_compare, in_shard};
+use crate::{Comparator, CompareOptions, ComparisonEvent, Error, FolderCompare};
+
+/// Keeps watching two directories after comparing them and classifies the files again as they
+/// change, available with the `notify` feature.
+///
+/// Every file touched in either directory is classified against its counterpart like
+/// [`FolderCompare::from_iter`] does and yields a [`ComparisonEvent`]. A file removed from
+/// both directories yields nothing, and one only left in the second directory yields a deleted
+/// event with [`CompareOptions::detect_deleted`] only. All files below a directory created,
+/// removed or renamed are classified again. Only files within [`CompareOptions::subpaths`] and
+/// [`CompareOptions::shard`] are classified. Writing a file usually raises several events, so
+/// the same file is often classified, and read, several times in a row.
+///
+/// # Example
+///
+///```no_run
+/// use std::path::Path;
+/// use folder_compare::{CompareOptions, FolderWatch};
+///
+/// let (result, watch) = FolderWatch::new(Path::new("/tmp/a"), Path::new("/tmp/b"), &vec![], &CompareOptions::default()).unwrap();
+///
+/// for event in watch {
+///     let event = event.unwrap();
+///     println!("{} is {:?}", event.path.display(), event.status);
+/// }
+///```
+///
+pub struct FolderWatch {
+    comparator: Comparator,
+    path1: PathBuf,
+    path2: PathBuf,
+    /// Both directories with all symbolic links resolved, as watchers may report changes below
+    /// them.
+    canonical: (PathBuf, PathBuf),
+    /// The options for walking changed directories, which are walked on their own rather than
+    /// within [`CompareOptions::subpaths`].
+    walk_options: CompareOptions,
+    /// Kept alive for as long as the directories are watched.
+    _watcher: notify::RecommendedWatcher,
+    changes: Receiver<notify::Result<notify::Event>>,
+    pending: VecDeque<ComparisonEvent>,
+}
+
+impl FolderWatch {
+    /// Starts watching `path1` and `path2` and compares them like [`FolderCompare::with_options`]
+    /// does. The watches are set up before comparing, so no change happening meanwhile is missed.
+    pub fn new(path1: &Path, path2: &Path, excluded: &Vec<String>, options: &CompareOptions) -> Result<(FolderCompare, Self), Error> {
+        let mut comparator = Comparator::new(excluded, options.clone())?;
+        let (sender, changes) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+        watcher.watch(path1, RecursiveMode::Recursive)?;
+        watcher.watch(path2, RecursiveMode::Recursive)?;
+        let result = comparator.compare(path1, path2)?;
+        let walk_options = CompareOptions { subpaths: vec![], shard: None, ..options.clone() };
+        Ok((result, FolderWatch {
+            comparator,
+            path1: path1.to_path_buf(),
+            path2: path2.to_path_buf(),
+            canonical: (path1.canonicalize()?, path2.canonicalize()?),
+            walk_options,
+            _watcher: watcher,
+            changes,
+            pending: VecDeque::new(),
+        }))
+    }
+
+    /// Waits up to `timeout` for the next event, e.g. for a loop that has to do other things
+    /// as well. Returns `None` if nothing changed in time.
+    pub fn next_timeout(&mut self, timeout: Duration) -> Option<Result<ComparisonEvent, Error>> {
+        self.receive(Some(timeout))
+    }
+
+    /// Waits for the next event, up to `timeout` if given. Returns `None` once the watcher is
+    /// gone or the time is up.
+    fn receive(&mut self, timeout: Option<Duration>) -> Option<Result<ComparisonEvent, Error>> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some(Ok(event));
+            }
+            let change = match timeout {
+                Some(timeout) => self.changes.recv_timeout(timeout).ok()?,
+                None => self.changes.recv().ok()?,
+            };
+            if let Err(error) = self.classify(change) {
+                return Some(Err(error));
+            }
+        }
+    }
+
+    /// Classifies the files touched by a `change` again and queues their events.
+    fn classify(&mut self, change: notify::Result<notify::Event>) -> Result<(), Error> {
+        let change = change?;
+        if matches!(change.kind, EventKind::Access(_)) {
+            return Ok(());
+        }
+        let mut relative = vec![];
+        for path in &change.paths {
+            let path = match [&self.path1, &self.path2, &self.canonical.0, &self.canonical.1].iter().find_map(|root| path.strip_prefix(root).ok()) {
+                Some(path) => path,
+                None => continue,
+            };
+            for root in [&self.path1, &self.path2] {
+                let dir = root.join(path);
+                if dir.is_dir() {
+                    let set = RegexSet::empty();
+                    relative.extend(files_to_compare(&dir, &set, &self.walk_options)
+                        .filter_map(|entry| entry.path().strip_prefix(root).ok().map(Path::to_path_buf)));
+                }
+            }
+            relative.push(path.to_path_buf());
+        }
+        let options = self.comparator.options();
+        relative.retain(|path| {
+            (options.subpaths.is_empty() || options.subpaths.iter().any(|subpath| path.starts_with(subpath)))
+                && in_shard(Path::new(""), path, options)
+        });
+        relative.sort();
+        relative.dedup();
+        let result = self.comparator.compare_paths(&self.path1, &self.path2, relative)?;
+        self.pending.extend(result.events());
+        Ok(())
+    }
+}
+
+impl Iterator for FolderWatch {
+    type Item = Result<ComparisonEvent, Error>;
+
+    /// Waits for the next event. Ends once the directories can't be watched any longer.
+    fn next(&mut self) -> Option<Self::Item> {
+        self.receive(None)
+    }
+}
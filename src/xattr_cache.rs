@@ -0,0 +1,50 @@
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+/// The extended attribute the hash of a file is cached in, see
+/// [`CompareOptions::use_xattr_cache`](crate::CompareOptions::use_xattr_cache).
+const KEY: &str = "user.folder_compare.hash";
+
+/// Reads the hash cached for the file at `path`, if there is one recorded for the modification
+/// time `modified`, as formatted by [`modified_stamp`].
+#[cfg(unix)]
+pub(crate) fn cached_hash(path: &Path, modified: &str) -> Option<u64> {
+    let value = xattr::get(path, KEY).ok()??;
+    let (cached_modified, hash) = std::str::from_utf8(&value).ok()?.split_once(':')?;
+    if cached_modified != modified {
+        return None;
+    }
+    u64::from_str_radix(hash, 16).ok()
+}
+
+/// Caches `hash` for the file at `path` along with `modified`, the modification time taken before
+/// hashing it. Nothing is cached if the file was modified since, as `hash` may then cover the old
+/// content. Failures, e.g. because of missing write permission or a file system without extended
+/// attributes, are ignored.
+#[cfg(unix)]
+pub(crate) fn store_hash(path: &Path, modified: &str, hash: u64) {
+    if modified_stamp(path).as_deref() == Some(modified) {
+        let _ = xattr::set(path, KEY, format!("{}:{:016x}", modified, hash).as_bytes());
+    }
+}
+
+/// Formats the modification time of the file at `path` as seconds and nanoseconds since the Unix
+/// epoch.
+#[cfg(unix)]
+pub(crate) fn modified_stamp(path: &Path) -> Option<String> {
+    let modified = path.metadata().ok()?.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some(format!("{}.{:09}", modified.as_secs(), modified.subsec_nanos()))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn cached_hash(_path: &Path, _modified: &str) -> Option<u64> {
+    None
+}
+
+#[cfg(not(unix))]
+pub(crate) fn store_hash(_path: &Path, _modified: &str, _hash: u64) {}
+
+#[cfg(not(unix))]
+pub(crate) fn modified_stamp(_path: &Path) -> Option<String> {
+    None
+}
@@ -1,9 +1,9 @@
-use folder_compare;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::{env, fs};
-use std::fs::{create_dir, remove_dir_all};
+use std::fs::{create_dir, remove_dir_all, File};
 use std::io::Error;
-use folder_compare::FolderCompare;
+use std::time::{Duration, SystemTime};
+use folder_compare::{files_equal, has_changed_since, hash_file, identical_files, tree_hash, tree_hash_seeded, verify_file, Comparator, CompareMode, CompareOptions, ConfigureWalk, DirStats, EntryKinds, EntryOrder, FileMetadata, FileSource, FileStatus, FolderCompare, Manifest, MetadataDifference, OrderBy, PlannedOp, Reclassify, ResultSink, Symlinks, SyncOp, WalkErrors};
 
 #[test]
 fn one_changed_one_new_one_ignored() {
@@ -15,9 +15,2042 @@ fn one_changed_one_new_one_ignored() {
     assert_eq!((result.changed_files.len(), result.new_files.len()), (1, 1));
 }
 
+#[test]
+fn operations_create_and_overwrite_before_delete() {
+    let dirs = prepare_environment_in("compare_operations").unwrap();
+    fs::write(dirs.1.join("test.old"), "Test").unwrap();
+    let excluded = vec![".txt".to_string()];
+    let options = CompareOptions {
+        detect_deleted: true,
+        ..Default::default()
+    };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &excluded, &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(result.as_operations(), vec![
+        SyncOp::Create(dirs.0.join("test.abc")),
+        SyncOp::Overwrite(dirs.0.join("test.xls")),
+        SyncOp::Delete(dirs.1.join("test.old")),
+    ]);
+}
+
+#[test]
+fn plan_sync_lists_sources_and_destinations() {
+    let dirs = prepare_environment_in("compare_plan_sync").unwrap();
+    fs::write(dirs.1.join("test.old"), "Test").unwrap();
+    let options = CompareOptions {
+        detect_deleted: true,
+        ..Default::default()
+    };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &options).unwrap();
+    let staging = dirs.1.parent().unwrap().join("staging");
+    let plan = result.plan_sync(dirs.0.as_path(), staging.as_path());
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(plan, vec![
+        PlannedOp { op: SyncOp::Create(dirs.0.join("test.abc")), source: Some(dirs.0.join("test.abc")), destination: staging.join("test.abc") },
+        PlannedOp { op: SyncOp::Overwrite(dirs.0.join("test.xls")), source: Some(dirs.0.join("test.xls")), destination: staging.join("test.xls") },
+        PlannedOp { op: SyncOp::Delete(dirs.1.join("test.old")), source: None, destination: staging.join("test.old") },
+    ]);
+}
+
+#[test]
+fn merge_join_matches_lookup_compare() {
+    let dirs = prepare_environment_in("compare_merge_join").unwrap();
+    fs::write(dirs.1.join("test.old"), "Test").unwrap();
+    let options = CompareOptions {
+        detect_deleted: true,
+        ..Default::default()
+    };
+    let lookup = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![], &options).unwrap();
+    let options = CompareOptions {
+        detect_deleted: true,
+        merge_join: true,
+        ..Default::default()
+    };
+    let mut merge_join = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![], &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    merge_join.new_files.sort();
+    let mut lookup_new = lookup.new_files.clone();
+    lookup_new.sort();
+    assert_eq!(merge_join.new_files, lookup_new);
+    assert_eq!(merge_join.changed_files, lookup.changed_files);
+    assert_eq!(merge_join.deleted_files, lookup.deleted_files);
+}
+
+#[cfg(feature = "parallel-walk")]
+#[test]
+fn parallel_walk_matches_sequential_walk() {
+    let dirs = prepare_environment_in("compare_parallel_walk").unwrap();
+    create_dir(dirs.0.join("nested")).unwrap();
+    fs::write(dirs.0.join("nested").join("test.abc"), "Test").unwrap();
+    fs::write(dirs.1.join("test.old"), "Test").unwrap();
+    let options = CompareOptions {
+        detect_deleted: true,
+        merge_join: true,
+        ..Default::default()
+    };
+    let sequential = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![], &options).unwrap();
+    let options = CompareOptions {
+        parallel_walk: true,
+        ..options
+    };
+    let parallel = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![], &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(parallel.new_files, sequential.new_files);
+    assert_eq!(parallel.changed_files, sequential.changed_files);
+    assert_eq!(parallel.unchanged_files, sequential.unchanged_files);
+    assert_eq!(parallel.deleted_files, sequential.deleted_files);
+}
+
+#[cfg(unix)]
+#[test]
+fn follow_directory_symlinks_only() {
+    let dirs = prepare_environment_in("compare_symlinks").unwrap();
+    let mut linked = dirs.0.parent().unwrap().to_path_buf();
+    linked.push("linked");
+    create_dir(&linked).unwrap();
+    fs::write(linked.join("linked.abc"), "Test").unwrap();
+    std::os::unix::fs::symlink(&linked, dirs.0.join("dir_link")).unwrap();
+    std::os::unix::fs::symlink(linked.join("linked.abc"), dirs.0.join("file_link.abc")).unwrap();
+
+    let options = CompareOptions {
+        symlinks: Symlinks::FollowDirectories,
+        ..Default::default()
+    };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![], &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert!(result.new_files.contains(&dirs.0.join("dir_link").join("linked.abc")));
+    assert!(!result.new_files.contains(&dirs.0.join("file_link.abc")));
+}
+
+#[cfg(unix)]
+#[test]
+fn skipped_symlinks_are_reported() {
+    let dirs = prepare_environment_in("compare_skipped_symlinks").unwrap();
+    create_dir(dirs.0.join("dir")).unwrap();
+    std::os::unix::fs::symlink(dirs.0.join("dir"), dirs.0.join("dir_link")).unwrap();
+    std::os::unix::fs::symlink(dirs.0.join("test.abc"), dirs.0.join("file_link.abc")).unwrap();
+    std::os::unix::fs::symlink(dirs.0.join("test.txt"), dirs.0.join("file_link.txt")).unwrap();
+    let options = CompareOptions {
+        report_skipped_symlinks: true,
+        ..Default::default()
+    };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    let mut skipped = result.skipped_symlinks;
+    skipped.sort();
+    assert_eq!(skipped, vec![dirs.0.join("dir_link"), dirs.0.join("file_link.abc")]);
+    assert!(!result.new_files.contains(&dirs.0.join("file_link.abc")));
+}
+
+#[cfg(unix)]
+#[test]
+fn left_out_entries_are_counted() {
+    let dirs = prepare_environment_in("compare_left_out_counts").unwrap();
+    create_dir(dirs.0.join("cache")).unwrap();
+    fs::write(dirs.0.join("cache").join("test.txt"), "Test").unwrap();
+    std::os::unix::fs::symlink(dirs.0.join("cache"), dirs.0.join("dir_link")).unwrap();
+    std::os::unix::fs::symlink(dirs.0.join("test.abc"), dirs.0.join("file_link.abc")).unwrap();
+    std::os::unix::fs::symlink(dirs.0.join("test.txt"), dirs.0.join("file_link.txt")).unwrap();
+    let options = CompareOptions {
+        prune_dir_names: vec!["cache".to_string()],
+        ..Default::default()
+    };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!((result.excluded_files_count, result.pruned_dirs_count, result.symlinks_skipped), (2, 1, 2));
+}
+
+#[cfg(unix)]
+#[test]
+fn permissions_are_compared_within_mask() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dirs = prepare_environment_in("compare_permission_mask").unwrap();
+    for (dir, xls_mode, abc_mode) in [(&dirs.0, 0o644, 0o755), (&dirs.1, 0o600, 0o644)] {
+        fs::write(dir.join("same.abc"), "Test").unwrap();
+        fs::set_permissions(dir.join("test.xls"), fs::Permissions::from_mode(xls_mode)).unwrap();
+        fs::set_permissions(dir.join("same.abc"), fs::Permissions::from_mode(abc_mode)).unwrap();
+    }
+    let options = CompareOptions { compare_permissions: true, permission_mask: Some(0o111), ..Default::default() };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(result.permissions_changed, vec![dirs.0.join("same.abc")]);
+}
+
+#[cfg(unix)]
+#[test]
+fn strict_metadata_counts_metadata_drift_as_change() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dirs = prepare_environment_in("compare_strict_metadata").unwrap();
+    let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+    for (dir, offset, mode) in [(&dirs.0, 0, 0o644), (&dirs.1, 5, 0o600)] {
+        for name in ["same.abc", "mtime.abc", "mode.abc"] {
+            fs::write(dir.join(name), "Test").unwrap();
+        }
+        File::options().write(true).open(dir.join("same.abc")).unwrap().set_modified(time).unwrap();
+        File::options().write(true).open(dir.join("mode.abc")).unwrap().set_modified(time).unwrap();
+        File::options().write(true).open(dir.join("mtime.abc")).unwrap().set_modified(time + Duration::from_secs(offset)).unwrap();
+        fs::set_permissions(dir.join("mode.abc"), fs::Permissions::from_mode(mode)).unwrap();
+        fs::set_permissions(dir.join("mtime.abc"), fs::Permissions::from_mode(0o644)).unwrap();
+        fs::set_permissions(dir.join("same.abc"), fs::Permissions::from_mode(0o644)).unwrap();
+    }
+    let options = CompareOptions { strict_metadata: true, ..Default::default() };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert!(result.unchanged_files.contains(&dirs.0.join("same.abc")));
+    assert!(result.changed_files.contains(&dirs.0.join("mtime.abc")));
+    assert!(result.changed_files.contains(&dirs.0.join("mode.abc")));
+    let mut differences = result.metadata_differences;
+    differences.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(differences, vec![
+        (dirs.0.join("mode.abc"), MetadataDifference::Permissions),
+        (dirs.0.join("mtime.abc"), MetadataDifference::Modified),
+    ]);
+}
+
+#[cfg(unix)]
+#[test]
+fn hard_linked_files_are_read_once() {
+    let dirs = prepare_environment_in("compare_dedupe_reads").unwrap();
+    fs::write(dirs.0.join("shared.abc"), "Shared").unwrap();
+    fs::hard_link(dirs.0.join("shared.abc"), dirs.1.join("shared.abc")).unwrap();
+    fs::write(dirs.0.join("one.abc"), "Copy").unwrap();
+    fs::hard_link(dirs.0.join("one.abc"), dirs.0.join("two.abc")).unwrap();
+    fs::write(dirs.1.join("one.abc"), "Copy").unwrap();
+    fs::write(dirs.1.join("two.abc"), "Copy").unwrap();
+    let options = CompareOptions { dedupe_reads: true, ..Default::default() };
+    let mut read = 0;
+    let result = FolderCompare::compare_with_progress_bytes(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &options, |bytes, _| {
+        read = bytes;
+    }).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(result.changed_files, vec![dirs.0.join("test.xls")]);
+    assert_eq!(result.unchanged_files.len(), 3);
+    assert_eq!(read, 4 + 4 + 4);
+}
+
+#[cfg(unix)]
+#[test]
+fn copies_of_the_same_size_are_still_read() {
+    let dirs = prepare_environment_in("compare_dedupe_copies").unwrap();
+    fs::write(dirs.0.join("one.abc"), "Copy").unwrap();
+    fs::write(dirs.1.join("one.abc"), "Copy").unwrap();
+    fs::write(dirs.0.join("two.abc"), "Copy").unwrap();
+    fs::write(dirs.1.join("two.abc"), "Cope").unwrap();
+    let options = CompareOptions { dedupe_reads: true, ..Default::default() };
+    let mut read = 0;
+    let result = FolderCompare::compare_with_progress_bytes(dirs.0.as_path(), dirs.1.as_path(), &vec!["test".to_string()], &options, |bytes, _| {
+        read = bytes;
+    }).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(result.changed_files, vec![dirs.0.join("two.abc")]);
+    assert_eq!(result.unchanged_files, vec![dirs.0.join("one.abc")]);
+    assert_eq!(read, 4 * 4);
+}
+
+#[cfg(unix)]
+#[test]
+fn symlinks_are_compared_by_target() {
+    let dirs = prepare_environment_in("compare_symlink_targets").unwrap();
+    for dir in [&dirs.0, &dirs.1] {
+        std::os::unix::fs::symlink("test.xls", dir.join("same.abc")).unwrap();
+        std::os::unix::fs::symlink("missing", dir.join("broken.abc")).unwrap();
+    }
+    std::os::unix::fs::symlink("test.abc", dirs.0.join("retargeted.abc")).unwrap();
+    std::os::unix::fs::symlink("test.xls", dirs.1.join("retargeted.abc")).unwrap();
+    std::os::unix::fs::symlink("test.xls", dirs.0.join("replaced.abc")).unwrap();
+    fs::write(dirs.1.join("replaced.abc"), "Test2").unwrap();
+    let options = CompareOptions {
+        symlinks: Symlinks::CompareTargets,
+        order_by: OrderBy::PathAsc,
+        ..Default::default()
+    };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(result.unchanged_files, vec![dirs.0.join("broken.abc"), dirs.0.join("same.abc")]);
+    assert_eq!(result.changed_files, vec![dirs.0.join("replaced.abc"), dirs.0.join("retargeted.abc"), dirs.0.join("test.xls")]);
+}
+
+#[cfg(unix)]
+#[test]
+fn broken_symlinks_are_reported_when_following() {
+    let dirs = prepare_environment_in("compare_broken_symlinks").unwrap();
+    std::os::unix::fs::symlink(dirs.0.join("missing"), dirs.0.join("broken.abc")).unwrap();
+    let options = CompareOptions {
+        symlinks: Symlinks::Follow,
+        ..Default::default()
+    };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![], &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(result.broken_symlinks, vec![dirs.0.join("broken.abc")]);
+}
+
+#[cfg(unix)]
+#[test]
+fn broken_symlinks_are_reported_once_for_both_roots() {
+    let dirs = prepare_environment_in("compare_broken_symlinks_both").unwrap();
+    std::os::unix::fs::symlink(dirs.1.join("missing"), dirs.1.join("test.abc")).unwrap();
+    std::os::unix::fs::symlink(dirs.1.join("missing"), dirs.1.join("only.abc")).unwrap();
+    let options = CompareOptions {
+        symlinks: Symlinks::Follow,
+        detect_deleted: true,
+        ..Default::default()
+    };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![], &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    let mut broken = result.broken_symlinks;
+    broken.sort();
+    assert_eq!(broken, vec![dirs.1.join("only.abc"), dirs.1.join("test.abc")]);
+    assert!(!result.new_files.contains(&dirs.0.join("test.abc")));
+}
+
+#[cfg(unix)]
+#[test]
+fn counterparts_escaping_the_root_are_rejected() {
+    let dirs = prepare_environment_in("compare_confined").unwrap();
+    let outside = dirs.1.parent().unwrap().join("outside");
+    create_dir(&outside).unwrap();
+    fs::write(outside.join("test.abc"), "Test").unwrap();
+    std::os::unix::fs::symlink("../outside", dirs.1.join("escape")).unwrap();
+    create_dir(dirs.0.join("escape")).unwrap();
+    fs::write(dirs.0.join("escape").join("test.abc"), "Test").unwrap();
+    let options = CompareOptions {
+        confine_to_roots: true,
+        ..Default::default()
+    };
+    let failed = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![], &options).is_err();
+    let options = CompareOptions {
+        continue_on_error: true,
+        ..options
+    };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![], &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    let escaped: Vec<_> = result.errors.iter().map(|error| error.path.clone()).collect();
+    assert!(failed);
+    assert_eq!(escaped, vec![dirs.1.join("escape").join("test.abc")]);
+    assert!(!result.unchanged_files.contains(&dirs.0.join("escape").join("test.abc")));
+}
+
+#[cfg(unix)]
+#[test]
+fn walk_errors_are_recorded_skipped_or_abort() {
+    let dirs = prepare_environment_in("compare_walk_errors").unwrap();
+    std::os::unix::fs::symlink(&dirs.0, dirs.0.join("loop")).unwrap();
+    let compare = |walk_errors| {
+        let options = CompareOptions {
+            symlinks: Symlinks::Follow,
+            walk_errors,
+            ..Default::default()
+        };
+        FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![], &options)
+    };
+    let recorded = compare(WalkErrors::Record).unwrap();
+    let skipped = compare(WalkErrors::Skip).unwrap();
+    let aborted = compare(WalkErrors::Abort);
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    let recorded: Vec<_> = recorded.errors.iter().map(|error| error.path.clone()).collect();
+    assert_eq!(recorded, vec![dirs.0.join("loop")]);
+    assert!(skipped.errors.is_empty());
+    assert!(aborted.is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn walk_errors_dont_advance_the_file_progress() {
+    let dirs = prepare_environment_in("compare_progress_walk_errors").unwrap();
+    std::os::unix::fs::symlink(&dirs.0, dirs.0.join("loop")).unwrap();
+    let options = CompareOptions {
+        symlinks: Symlinks::Follow,
+        walk_errors: WalkErrors::Record,
+        ..Default::default()
+    };
+    let mut steps = vec![];
+    let result = FolderCompare::compare_with_progress_files(dirs.0.as_path(), dirs.1.as_path(), &vec![], &options, |done, total| {
+        steps.push((done, total));
+    }).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(result.errors.len(), 1);
+    assert_eq!(steps, vec![(1, 3), (2, 3), (3, 3)]);
+}
+
+#[cfg(unix)]
+#[test]
+fn hardlinks_split_into_copies_are_reported() {
+    let dirs = prepare_environment_in("compare_hardlinks").unwrap();
+    fs::hard_link(dirs.0.join("test.abc"), dirs.0.join("link.abc")).unwrap();
+    fs::write(dirs.1.join("test.abc"), "Test").unwrap();
+    fs::write(dirs.1.join("link.abc"), "Test").unwrap();
+    let options = CompareOptions {
+        compare_hardlinks: true,
+        ..Default::default()
+    };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![], &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    let mut changed = result.hardlink_structure_changed;
+    changed.sort();
+    assert_eq!(changed, vec![dirs.0.join("link.abc"), dirs.0.join("test.abc")]);
+}
+
+#[test]
+fn truncated_counterparts_are_partial() {
+    let dirs = prepare_environment_in("compare_partial").unwrap();
+    fs::write(dirs.0.join("test.xls"), "Test2 and more").unwrap();
+    fs::write(dirs.1.join("test.abc"), "Tesu").unwrap();
+    fs::write(dirs.1.join("test.txt"), "Tx").unwrap();
+    let options = CompareOptions {
+        detect_partial: true,
+        ..Default::default()
+    };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![], &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(result.partial, vec![(dirs.0.join("test.xls"), 9)]);
+    let mut changed = result.changed_files;
+    changed.sort();
+    assert_eq!(changed, vec![dirs.0.join("test.abc"), dirs.0.join("test.txt")]);
+}
+
+#[test]
+fn partial_files_reach_sinks_and_first_difference() {
+    #[derive(Default)]
+    struct Partial(Vec<(PathBuf, u64)>);
+
+    impl ResultSink for Partial {
+        fn on_partial(&mut self, path: &Path, missing: u64) {
+            self.0.push((path.to_path_buf(), missing));
+        }
+    }
+
+    let dirs = prepare_environment_in("compare_partial_sink").unwrap();
+    fs::write(dirs.0.join("test.xls"), "Test2 and more").unwrap();
+    let excluded = vec!["test\\.(abc|txt)".to_string()];
+    let options = CompareOptions { detect_partial: true, max_memory: Some(8), ..Default::default() };
+    let mut comparator = Comparator::new(&excluded, options).unwrap();
+    let mut sink = Partial::default();
+    comparator.compare_into(dirs.0.as_path(), dirs.1.as_path(), &mut sink).unwrap();
+    let first = comparator.first_difference(dirs.0.as_path(), dirs.1.as_path()).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(sink.0, vec![(dirs.0.join("test.xls"), 9)]);
+    assert_eq!(first, Some((dirs.0.join("test.xls"), FileStatus::Changed)));
+}
+
+#[test]
+fn files_larger_than_max_memory_are_compared_chunk_by_chunk() {
+    let dirs = prepare_environment_in("compare_max_memory").unwrap();
+    fs::write(dirs.0.join("test.abc"), "Test".repeat(20)).unwrap();
+    fs::write(dirs.1.join("test.abc"), "Test".repeat(20)).unwrap();
+    fs::write(dirs.0.join("test.txt"), "Test".repeat(20)).unwrap();
+    fs::write(dirs.1.join("test.txt"), format!("{}Tesd", "Test".repeat(19))).unwrap();
+    let options = CompareOptions { max_memory: Some(12), ..Default::default() };
+    let mut comparator = Comparator::new(&vec![], options).unwrap();
+    let result = comparator.compare(dirs.0.as_path(), dirs.1.as_path()).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(result.unchanged_files, vec![dirs.0.join("test.abc")]);
+    let mut changed = result.changed_files;
+    changed.sort();
+    assert_eq!(changed, vec![dirs.0.join("test.txt"), dirs.0.join("test.xls")]);
+}
+
+#[test]
+fn changed_files_by_size_largest_first() {
+    let dirs = prepare_environment_in("compare_changed_by_size").unwrap();
+    fs::write(dirs.0.join("test.abc"), "Larger test").unwrap();
+    fs::write(dirs.1.join("test.abc"), "Test").unwrap();
+    let result = FolderCompare::new(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()]).unwrap();
+    let sized = result.changed_by_size();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(sized, vec![(dirs.0.join("test.abc"), 11), (dirs.0.join("test.xls"), 4)]);
+}
+
+#[test]
+fn comparator_is_reused_across_roots() {
+    let first = prepare_environment_in("compare_reused_first").unwrap();
+    let second = prepare_environment_in("compare_reused_second").unwrap();
+    fs::write(second.1.join("test.abc"), "Test").unwrap();
+    let mut comparator = Comparator::new(&vec![".txt".to_string()], CompareOptions::default()).unwrap();
+    let result1 = comparator.compare(first.0.as_path(), first.1.as_path()).unwrap();
+    let result2 = comparator.compare(second.0.as_path(), second.1.as_path()).unwrap();
+
+    remove_dir_all(first.1.parent().unwrap()).unwrap();
+    remove_dir_all(second.1.parent().unwrap()).unwrap();
+    assert_eq!((result1.path1, result1.path2), (first.0.clone(), first.1));
+    assert_eq!(result1.new_files, vec![first.0.join("test.abc")]);
+    assert_eq!(result1.changed_files, vec![first.0.join("test.xls")]);
+    assert_eq!((result2.path1, result2.path2), (second.0.clone(), second.1));
+    assert!(result2.new_files.is_empty());
+    assert_eq!(result2.unchanged_files, vec![second.0.join("test.abc")]);
+    assert_eq!(result2.changed_files, vec![second.0.join("test.xls")]);
+}
+
+#[test]
+fn entries_are_passed_to_a_custom_sink() {
+    struct Collect(Vec<(FileStatus, PathBuf)>);
+
+    impl ResultSink for Collect {
+        fn on_new(&mut self, path: &Path) {
+            self.0.push((FileStatus::New, path.to_path_buf()));
+        }
+
+        fn on_changed(&mut self, path: &Path) {
+            self.0.push((FileStatus::Changed, path.to_path_buf()));
+        }
+
+        fn on_deleted(&mut self, path: &Path) {
+            self.0.push((FileStatus::Deleted, path.to_path_buf()));
+        }
+    }
+
+    let dirs = prepare_environment_in("compare_custom_sink").unwrap();
+    fs::write(dirs.1.join("test.old"), "Test").unwrap();
+    let options = CompareOptions { detect_deleted: true, ..Default::default() };
+    let mut comparator = Comparator::new(&vec![".txt".to_string()], options).unwrap();
+    let mut sink = Collect(vec![]);
+    comparator.compare_into(dirs.0.as_path(), dirs.1.as_path(), &mut sink).unwrap();
+    sink.0.sort_by(|a, b| a.1.cmp(&b.1));
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(sink.0, vec![
+        (FileStatus::New, dirs.0.join("test.abc")),
+        (FileStatus::Changed, dirs.0.join("test.xls")),
+        (FileStatus::Deleted, dirs.1.join("test.old")),
+    ]);
+}
+
+#[test]
+fn first_difference_stops_at_the_first_change() {
+    let dirs = prepare_environment_in("compare_first_difference").unwrap();
+    fs::write(dirs.1.join("test.abc"), "Test").unwrap();
+    let mut comparator = Comparator::new(&vec![".txt".to_string()], CompareOptions::default()).unwrap();
+    let first = comparator.first_difference(dirs.0.as_path(), dirs.1.as_path()).unwrap();
+    fs::write(dirs.1.join("test.xls"), "Test").unwrap();
+    let none = comparator.first_difference(dirs.0.as_path(), dirs.1.as_path()).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(first, Some((dirs.0.join("test.xls"), FileStatus::Changed)));
+    assert_eq!(none, None);
+}
+
+#[test]
+fn first_difference_leaves_the_options_alone_when_panicking() {
+    let dirs = prepare_environment_in("compare_first_difference_panic").unwrap();
+    let options = CompareOptions {
+        reclassify: Some(Reclassify::new(|_, _| panic!("reclassify failed"))),
+        detect_same_content: true,
+        ..Default::default()
+    };
+    let mut comparator = Comparator::new(&vec![".txt".to_string()], options).unwrap();
+    let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        comparator.first_difference(dirs.0.as_path(), dirs.1.as_path())
+    })).is_err();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert!(panicked);
+    assert_eq!(comparator.options().limit, None);
+    assert!(comparator.options().detect_same_content);
+}
+
+#[test]
+fn compare_into_refills_the_result() {
+    let dirs = prepare_environment_in("compare_into").unwrap();
+    let excluded = vec![".txt".to_string()];
+    let mut result = FolderCompare::default();
+    result.deleted_files.push(dirs.1.join("stale"));
+    FolderCompare::compare_into(dirs.0.as_path(), dirs.1.as_path(), &excluded, &mut result).unwrap();
+    fs::write(dirs.1.join("test.abc"), "Test").unwrap();
+    FolderCompare::compare_into(dirs.0.as_path(), dirs.1.as_path(), &excluded, &mut result).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert!(result.deleted_files.is_empty());
+    assert!(result.new_files.is_empty());
+    assert_eq!(result.unchanged_files, vec![dirs.0.join("test.abc")]);
+    assert_eq!(result.changed_files, vec![dirs.0.join("test.xls")]);
+}
+
+#[test]
+fn lines_matching_the_ignored_pattern_are_left_out() {
+    let dirs = prepare_environment_in("compare_ignore_lines").unwrap();
+    fs::write(dirs.0.join("config.ini"), "Generated: 2024-01-01\r\nkey=value\n").unwrap();
+    fs::write(dirs.1.join("config.ini"), "Generated: 2024-02-02\r\nkey=value\n").unwrap();
+    fs::write(dirs.0.join("other.ini"), "Generated: 2024-01-01\nkey=value\n").unwrap();
+    fs::write(dirs.1.join("other.ini"), "Generated: 2024-01-01\nkey=other\n").unwrap();
+    let options = CompareOptions {
+        ignore_line_pattern: Some("^Generated: .*$".to_string()),
+        ..Default::default()
+    };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(result.unchanged_files, vec![dirs.0.join("config.ini")]);
+    let mut changed = result.changed_files;
+    changed.sort();
+    assert_eq!(changed, vec![dirs.0.join("other.ini"), dirs.0.join("test.xls")]);
+}
+
+#[test]
+fn changes_only_leaves_out_new_files() {
+    let dirs = prepare_environment_in("compare_changes_only").unwrap();
+    let options = CompareOptions {
+        changes_only: true,
+        ..Default::default()
+    };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert!(result.new_files.is_empty());
+    assert_eq!(result.changed_files, vec![dirs.0.join("test.xls")]);
+}
+
+#[test]
+fn comparison_stops_after_max_bytes_read() {
+    let dirs = prepare_environment_in("compare_max_bytes_read").unwrap();
+    for dir in [&dirs.0, &dirs.1] {
+        fs::write(dir.join("one.abc"), "Test").unwrap();
+        fs::write(dir.join("two.abc"), "Test").unwrap();
+    }
+    let options = CompareOptions {
+        max_bytes_read: Some(1),
+        order: Some(EntryOrder::by_file_name()),
+        ..Default::default()
+    };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert!(result.truncated);
+    assert_eq!(result.unchanged_files, vec![dirs.0.join("one.abc")]);
+    assert!(result.changed_files.is_empty());
+}
+
+#[test]
+fn differing_creation_times_are_reported() {
+    let dirs = prepare_environment_in("compare_created").unwrap();
+    fs::write(dirs.0.join("same.abc"), "Test").unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    fs::write(dirs.1.join("same.abc"), "Test").unwrap();
+    let created = |path: PathBuf| fs::metadata(path).and_then(|m| m.created()).ok();
+    let supported = created(dirs.0.join("same.abc")).is_some() && created(dirs.1.join("same.abc")).is_some();
+    let options = CompareOptions {
+        compare_created: true,
+        ..Default::default()
+    };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert!(result.unchanged_files.contains(&dirs.0.join("same.abc")));
+    if supported {
+        assert_eq!(result.created_changed, vec![dirs.0.join("same.abc")]);
+    } else {
+        assert!(result.created_changed.is_empty());
+    }
+}
+
+#[test]
+fn pruned_directories_are_left_out_at_any_depth() {
+    let dirs = prepare_environment_in("compare_prune_dir_names").unwrap();
+    fs::create_dir_all(dirs.0.join("nested").join("node_modules")).unwrap();
+    fs::write(dirs.0.join("nested").join("node_modules").join("test.abc"), "Test").unwrap();
+    create_dir(dirs.0.join("node_modules")).unwrap();
+    fs::write(dirs.0.join("node_modules").join("test.abc"), "Test").unwrap();
+    fs::write(dirs.0.join("node_modules.abc"), "Test").unwrap();
+    let options = CompareOptions {
+        prune_dir_names: vec!["node_modules".to_string()],
+        order_by: OrderBy::PathAsc,
+        ..Default::default()
+    };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(result.new_files, vec![dirs.0.join("node_modules.abc"), dirs.0.join("test.abc")]);
+}
+
+#[test]
+fn empty_files_are_unchanged_only_against_empty_files() {
+    let dirs = prepare_environment_in("compare_empty_files").unwrap();
+    fs::write(dirs.0.join("both.abc"), "").unwrap();
+    fs::write(dirs.1.join("both.abc"), "").unwrap();
+    fs::write(dirs.0.join("first.abc"), "").unwrap();
+    fs::write(dirs.1.join("first.abc"), "Test").unwrap();
+    fs::write(dirs.0.join("second.abc"), "Test").unwrap();
+    fs::write(dirs.1.join("second.abc"), "").unwrap();
+    let results: Vec<FolderCompare> = [false, true].iter().map(|&empty_files_match| {
+        let options = CompareOptions {
+            empty_files_match,
+            order_by: OrderBy::PathAsc,
+            ..Default::default()
+        };
+        FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &options).unwrap()
+    }).collect();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    for result in results {
+        assert_eq!(result.unchanged_files, vec![dirs.0.join("both.abc")]);
+        assert_eq!(result.changed_files, vec![dirs.0.join("first.abc"), dirs.0.join("second.abc"), dirs.0.join("test.xls")]);
+    }
+}
+
+#[test]
+fn metadata_is_collected_along_with_the_status() {
+    let dirs = prepare_environment_in("compare_collect_metadata").unwrap();
+    let options = CompareOptions {
+        collect_metadata: true,
+        order: Some(EntryOrder::by_file_name()),
+        ..Default::default()
+    };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    let entries: Vec<_> = result.entries.iter().map(|e| (e.path.clone(), e.status, e.size, e.modified.is_some())).collect();
+    assert_eq!(entries, vec![
+        (dirs.0.join("test.abc"), FileStatus::New, 4, true),
+        (dirs.0.join("test.xls"), FileStatus::Changed, 4, true),
+    ]);
+}
+
+#[test]
+fn excludes_differ_per_side() {
+    let dirs = prepare_environment_in("compare_excluded_per_side").unwrap();
+    fs::write(dirs.0.join("test.txt"), "Test").unwrap();
+    fs::write(dirs.1.join("test.txt"), "Test").unwrap();
+    fs::write(dirs.1.join(".gitignore"), "target").unwrap();
+    let options = CompareOptions {
+        detect_deleted: true,
+        excluded_first: vec![".gitignore".to_string()],
+        excluded_second: vec![".txt".to_string()],
+        ..Default::default()
+    };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![".abc".to_string()], &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    let mut new = result.new_files;
+    new.sort();
+    assert_eq!(new, vec![dirs.0.join("test.abc"), dirs.0.join("test.txt")]);
+    assert_eq!(result.deleted_files, vec![dirs.1.join(".gitignore")]);
+}
+
+#[test]
+fn merged_shards_match_the_full_comparison() {
+    let dirs = prepare_environment_in("compare_shards").unwrap();
+    for name in ["one.abc", "two.abc", "three.abc"] {
+        fs::write(dirs.0.join(name), "Test").unwrap();
+        fs::write(dirs.1.join(name), "Other").unwrap();
+    }
+    let options = CompareOptions {
+        order_by: OrderBy::PathAsc,
+        ..Default::default()
+    };
+    let full = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![], &options).unwrap();
+    let mut merged = FolderCompare::default();
+    for index in 0..3 {
+        let options = CompareOptions {
+            shard: Some((index, 3)),
+            ..options.clone()
+        };
+        merged.merge(FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![], &options).unwrap());
+    }
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    merged.new_files.sort();
+    merged.changed_files.sort();
+    assert_eq!(merged.new_files, full.new_files);
+    assert_eq!(merged.changed_files, full.changed_files);
+}
+
+#[test]
+fn result_ordered_by_size_descending() {
+    let dirs = prepare_environment_in("compare_order_by_size").unwrap();
+    fs::write(dirs.0.join("large.abc"), "Larger test").unwrap();
+    fs::write(dirs.0.join("empty.abc"), "").unwrap();
+    let options = CompareOptions {
+        order_by: OrderBy::SizeDesc,
+        ..Default::default()
+    };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(result.new_files, vec![dirs.0.join("large.abc"), dirs.0.join("test.abc"), dirs.0.join("empty.abc")]);
+}
+
+#[cfg(all(feature = "sparse", target_os = "linux"))]
+#[test]
+fn sparse_files_match_allocated_copies() {
+    let dirs = prepare_environment_in("compare_sparse").unwrap();
+    let mut content = vec![0; 1 << 20];
+    content[..4].copy_from_slice(b"Test");
+    content[(1 << 20) - 4..].copy_from_slice(b"Tail");
+    fs::write(dirs.1.join("sparse.img"), &content).unwrap();
+    content[(1 << 20) - 4..].copy_from_slice(b"Tale");
+    fs::write(dirs.1.join("changed.img"), &content).unwrap();
+    for name in ["sparse.img", "changed.img"] {
+        let file = fs::File::create(dirs.0.join(name)).unwrap();
+        file.set_len(1 << 20).unwrap();
+        std::os::unix::fs::FileExt::write_at(&file, b"Test", 0).unwrap();
+        std::os::unix::fs::FileExt::write_at(&file, b"Tail", (1 << 20) - 4).unwrap();
+    }
+    let options = CompareOptions {
+        sparse_files: true,
+        ..Default::default()
+    };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert!(result.unchanged_files.contains(&dirs.0.join("sparse.img")));
+    assert!(result.changed_files.contains(&dirs.0.join("changed.img")));
+}
+
+#[test]
+fn roots_that_are_files_are_rejected() {
+    let dirs = prepare_environment_in("compare_file_roots").unwrap();
+    let first = FolderCompare::new(dirs.0.join("test.abc").as_path(), dirs.1.as_path(), &vec![]);
+    let second = FolderCompare::new(dirs.0.as_path(), dirs.1.join("test.xls").as_path(), &vec![]);
+    let missing = FolderCompare::new(dirs.0.as_path(), dirs.1.join("missing").as_path(), &vec![]);
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert!(matches!(first, Err(folder_compare::Error::NotADirectory(ref path)) if *path == dirs.0.join("test.abc")));
+    assert!(matches!(second, Err(folder_compare::Error::NotADirectory(ref path)) if *path == dirs.1.join("test.xls")));
+    assert!(matches!(missing, Err(folder_compare::Error::NotADirectory(ref path)) if *path == dirs.1.join("missing")));
+}
+
+#[test]
+fn seeded_tree_hashes_differ_from_unseeded_ones() {
+    let dirs = prepare_environment_in("compare_tree_hash_seeded").unwrap();
+    let unseeded = tree_hash(&dirs.0, &vec![]).unwrap();
+    let seeded = [0, 1].map(|seed| tree_hash_seeded(&dirs.0, &vec![], seed).unwrap());
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_ne!(seeded[0], unseeded);
+    assert_ne!(seeded[1], unseeded);
+    assert_ne!(seeded[0], seeded[1]);
+}
+
+#[test]
+fn tree_hash_matches_for_identical_trees_only() {
+    let dirs = prepare_environment_in("compare_tree_hash").unwrap();
+    fs::write(dirs.1.join("test.abc"), "Test").unwrap();
+    fs::write(dirs.1.join("test.xls"), "Test").unwrap();
+    let before = (tree_hash(&dirs.0, &vec![".txt".to_string()]).unwrap(), tree_hash(&dirs.1, &vec![]).unwrap());
+    fs::write(dirs.1.join("test.xls"), "Test2").unwrap();
+    let after = tree_hash(&dirs.1, &vec![]).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(before.0, before.1);
+    assert_ne!(before.1, after);
+}
+
+#[cfg(feature = "cdc")]
+#[test]
+fn chunks_survive_inserted_bytes() {
+    let dirs = prepare_environment_in("compare_cdc").unwrap();
+    let mut state: u32 = 1;
+    let content: Vec<u8> = (0..200_000).map(|_| {
+        state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+        (state >> 16) as u8
+    }).collect();
+    fs::write(dirs.0.join("image.bin"), &content).unwrap();
+    let mut shifted = b"Inserted header".to_vec();
+    shifted.extend_from_slice(&content);
+    fs::write(dirs.1.join("image.bin"), &shifted).unwrap();
+    let chunks = folder_compare::file_chunks(&dirs.0.join("image.bin")).unwrap();
+    let shared = folder_compare::shared_chunk_bytes(&dirs.0, &dirs.1, &vec![".abc".to_string(), ".xls".to_string(), ".txt".to_string()]).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(chunks.iter().map(|chunk| chunk.length).sum::<u64>(), 200_000);
+    assert!(chunks.len() > 1);
+    assert!(shared >= 200_000 - chunks[0].length);
+}
+
+#[cfg(feature = "archive")]
+#[test]
+fn zip_archives_are_compared_with_directories() {
+    use std::io::{Cursor, Write};
+    use folder_compare::{DirSource, ZipSource};
+    use zip::write::SimpleFileOptions;
+
+    let dirs = prepare_environment_in("compare_zip").unwrap();
+    let mut writer = zip::ZipWriter::new(Cursor::new(vec![]));
+    for (name, content) in [("test.abc", "Test"), ("test.xls", "Tesu"), ("sub/added.abc", "Test")] {
+        writer.start_file(name, SimpleFileOptions::default()).unwrap();
+        writer.write_all(content.as_bytes()).unwrap();
+    }
+    let archive = writer.finish().unwrap();
+    let mut first = ZipSource::new(Cursor::new(archive.into_inner())).unwrap();
+    let mut second = DirSource::new(&dirs.0);
+    let result = FolderCompare::from_sources(&mut first, &mut second, &vec![]).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(result.unchanged_files, vec![PathBuf::from("test.abc")]);
+    assert_eq!(result.changed_files, vec![PathBuf::from("test.xls")]);
+    assert_eq!(result.new_files, vec![PathBuf::from("sub/added.abc")]);
+}
+
+#[cfg(feature = "blake3")]
+#[test]
+fn large_files_are_hashed_in_parallel() {
+    let dirs = prepare_environment_in("compare_parallel_hash").unwrap();
+    let content: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+    for dir in [&dirs.0, &dirs.1] {
+        fs::write(dir.join("image.bin"), &content).unwrap();
+    }
+    fs::write(dirs.1.join("test.xls"), "Tesu").unwrap();
+    let options = CompareOptions { parallel_hash_threshold: Some(5), max_memory: Some(4096), ..Default::default() };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(result.changed_files, vec![dirs.0.join("test.xls")]);
+    assert_eq!(result.unchanged_files, vec![dirs.0.join("image.bin")]);
+    let (_, hash1, hash2) = result.changed_hashes[0];
+    assert_ne!(hash1, hash2);
+}
+
+#[cfg(feature = "git")]
+#[test]
+fn git_trees_are_compared_by_object_ids() {
+    use std::process::Command;
+
+    let dirs = prepare_environment_in("compare_git_trees").unwrap();
+    let git = |args: &[&str]| {
+        let status = Command::new("git").args(["-c", "user.name=test", "-c", "user.email=test@example.com"]).args(args).current_dir(&dirs.0).status().unwrap();
+        assert!(status.success());
+    };
+    fs::write(dirs.0.join("tool.sh"), "Test").unwrap();
+    git(&["init", "-q"]);
+    git(&["add", "test.xls", "test.txt", "tool.sh"]);
+    git(&["commit", "-q", "-m", "first"]);
+    fs::write(dirs.0.join("test.xls"), "Test2").unwrap();
+    git(&["update-index", "--chmod=+x", "tool.sh"]);
+    git(&["add", "test.abc", "test.xls"]);
+    git(&["commit", "-q", "-m", "second"]);
+    let result = folder_compare::compare_git_trees(&dirs.0, "HEAD", "HEAD~1", &vec![".txt".to_string()]).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(result.new_files, vec![PathBuf::from("test.abc")]);
+    assert_eq!(result.changed_files, vec![PathBuf::from("test.xls"), PathBuf::from("tool.sh")]);
+    assert!(result.unchanged_files.is_empty());
+}
+
+#[cfg(all(unix, feature = "xattr-cache"))]
+#[test]
+fn hashes_are_cached_for_the_first_directory_only() {
+    let dirs = prepare_environment_in("compare_xattr_cache").unwrap();
+    let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+    let rewrite = |path: PathBuf| {
+        fs::write(&path, "Tesu").unwrap();
+        File::options().write(true).open(path).unwrap().set_modified(time).unwrap();
+    };
+    for dir in [&dirs.0, &dirs.1] {
+        for name in ["first.abc", "second.abc"] {
+            fs::write(dir.join(name), "Test").unwrap();
+            File::options().write(true).open(dir.join(name)).unwrap().set_modified(time).unwrap();
+        }
+    }
+    let options = CompareOptions { use_xattr_cache: true, ..Default::default() };
+    let excluded = vec![".txt".to_string()];
+    FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &excluded, &options).unwrap();
+    rewrite(dirs.0.join("first.abc"));
+    rewrite(dirs.1.join("second.abc"));
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &excluded, &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert!(result.unchanged_files.contains(&dirs.0.join("first.abc")));
+    assert!(result.changed_files.contains(&dirs.0.join("second.abc")));
+}
+
+#[cfg(feature = "notify")]
+#[test]
+fn watched_changes_are_classified() {
+    let dirs = prepare_environment_in("compare_watch").unwrap();
+    let excluded = vec![".txt".to_string()];
+    let (result, mut watch) = folder_compare::FolderWatch::new(&dirs.0, &dirs.1, &excluded, &CompareOptions::default()).unwrap();
+    fs::write(dirs.1.join("test.xls"), "Test").unwrap();
+    let mut synced = None;
+    while let Some(event) = watch.next_timeout(Duration::from_secs(10)) {
+        let event = event.unwrap();
+        if event.status == FileStatus::Unchanged {
+            synced = Some(event.path);
+            break;
+        }
+    }
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(result.changed_files, vec![dirs.0.join("test.xls")]);
+    assert_eq!(synced, Some(dirs.0.join("test.xls")));
+}
+
+#[cfg(feature = "notify")]
+#[test]
+fn watched_directories_are_walked_within_subpaths() {
+    let dirs = prepare_environment_in("compare_watch_subpaths").unwrap();
+    for dir in [&dirs.0, &dirs.1] {
+        create_dir(dir.join("src")).unwrap();
+    }
+    let staged = dirs.0.parent().unwrap().join("staged");
+    create_dir(&staged).unwrap();
+    fs::write(staged.join("added.abc"), "Test").unwrap();
+    let options = CompareOptions { subpaths: vec![PathBuf::from("src")], ..Default::default() };
+    let (_, mut watch) = folder_compare::FolderWatch::new(&dirs.0, &dirs.1, &vec![], &options).unwrap();
+    fs::rename(&staged, dirs.0.join("src").join("moved")).unwrap();
+    let mut added = None;
+    while let Some(event) = watch.next_timeout(Duration::from_secs(10)) {
+        let event = event.unwrap();
+        if event.status == FileStatus::New {
+            added = Some(event.path);
+            break;
+        }
+    }
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(added, Some(dirs.0.join("src").join("moved").join("added.abc")));
+}
+
+#[test]
+fn directories_with_different_content_sets_are_reported() {
+    let dirs = prepare_environment_in("compare_content_sets").unwrap();
+    for (dir, names) in [(&dirs.0, ["one", "two", "three"]), (&dirs.1, ["three", "one", "two"])] {
+        create_dir(dir.join("renamed")).unwrap();
+        for (name, content) in names.iter().zip(["1", "2", "3"]) {
+            fs::write(dir.join("renamed").join(name), content).unwrap();
+        }
+    }
+    let options = CompareOptions {
+        compare_content_sets: true,
+        ..Default::default()
+    };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(result.content_set_changed, vec![dirs.0.clone()]);
+}
+
+#[test]
+fn hashed_file_names_make_content_sets_rename_sensitive() {
+    let dirs = prepare_environment_in("compare_hash_file_names").unwrap();
+    for (dir, names) in [(&dirs.0, ["one", "two"]), (&dirs.1, ["two", "one"])] {
+        create_dir(dir.join("renamed")).unwrap();
+        for (name, content) in names.iter().zip(["1", "2"]) {
+            fs::write(dir.join("renamed").join(name), content).unwrap();
+        }
+    }
+    let options = CompareOptions {
+        compare_content_sets: true,
+        hash_file_names: true,
+        ..Default::default()
+    };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(result.content_set_changed, vec![dirs.0.clone(), dirs.0.join("renamed")]);
+}
+
+#[test]
+fn files_of_different_sizes_are_not_read() {
+    let dirs = prepare_environment_in("compare_size_short_circuit").unwrap();
+    let mut read = 0;
+    let result = FolderCompare::compare_with_progress_bytes(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &CompareOptions::default(), |bytes, _| {
+        read = bytes;
+    }).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(result.changed_files, vec![dirs.0.join("test.xls")]);
+    assert_eq!(read, 0);
+}
+
+#[test]
+fn files_equal_by_content_and_size() {
+    let dirs = prepare_environment_in("compare_files_equal").unwrap();
+    fs::write(dirs.1.join("test.abc"), "Tesu").unwrap();
+    let same = files_equal(&dirs.0.join("test.abc"), &dirs.0.join("test.txt"), CompareMode::Content).unwrap();
+    let same_size = files_equal(&dirs.0.join("test.abc"), &dirs.1.join("test.abc"), CompareMode::Content).unwrap();
+    let same_size_by_size = files_equal(&dirs.0.join("test.abc"), &dirs.1.join("test.abc"), CompareMode::Size).unwrap();
+    let other_size = files_equal(&dirs.0.join("test.xls"), &dirs.1.join("test.xls"), CompareMode::Content).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!((same, same_size, same_size_by_size, other_size), (true, false, true, false));
+}
+
+#[test]
+fn recompare_against_saved_manifest() {
+    let dirs = prepare_environment_in("compare_manifest").unwrap();
+    let excluded = vec![".txt".to_string()];
+    let mut saved = vec![];
+    Manifest::create(dirs.0.as_path(), &excluded).unwrap().write_to(&mut saved).unwrap();
+    fs::write(dirs.0.join("test.xls"), "Changed").unwrap();
+    fs::write(dirs.0.join("test.new"), "Test").unwrap();
+    fs::remove_file(dirs.0.join("test.abc")).unwrap();
+    let prior = Manifest::read_from(saved.as_slice()).unwrap();
+    let (result, manifest) = FolderCompare::recompare(dirs.0.as_path(), &prior, &excluded).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(result.changed_files, vec![dirs.0.join("test.xls")]);
+    assert_eq!(result.new_files, vec![dirs.0.join("test.new")]);
+    assert_eq!(result.deleted_files, vec![PathBuf::from("test.abc")]);
+    assert_eq!(manifest.entries.len(), 2);
+}
+
+#[test]
+fn events_are_sorted_by_relative_path() {
+    let dirs = prepare_environment_in("compare_events").unwrap();
+    fs::write(dirs.1.join("test.def"), "Test").unwrap();
+    let options = CompareOptions {
+        detect_deleted: true,
+        ..Default::default()
+    };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    let events: Vec<_> = result.events().into_iter().map(|e| (e.path, e.status)).collect();
+    assert_eq!(events, vec![
+        (dirs.0.join("test.abc"), FileStatus::New),
+        (dirs.1.join("test.def"), FileStatus::Deleted),
+        (dirs.0.join("test.xls"), FileStatus::Changed),
+    ]);
+}
+
+#[test]
+fn dir_stats_count_entries_below_each_directory() {
+    let dirs = prepare_environment_in("compare_dir_stats").unwrap();
+    create_dir(dirs.0.join("nested")).unwrap();
+    fs::write(dirs.0.join("nested").join("test.abc"), "Test").unwrap();
+    let result = FolderCompare::new(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()]).unwrap();
+    let stats = result.dir_stats();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(stats.len(), 2);
+    assert_eq!(stats[&PathBuf::new()], DirStats { new: 2, changed: 1, unchanged: 0, deleted: 0 });
+    assert_eq!(stats[&PathBuf::from("nested")], DirStats { new: 1, ..Default::default() });
+}
+
+#[test]
+fn diff_tree_aggregates_directory_status() {
+    let dirs = prepare_environment_in("compare_diff_tree").unwrap();
+    for dir in [&dirs.0, &dirs.1] {
+        create_dir(dir.join("same")).unwrap();
+        fs::write(dir.join("same").join("test.abc"), "Test").unwrap();
+    }
+    create_dir(dirs.0.join("added")).unwrap();
+    fs::write(dirs.0.join("added").join("test.abc"), "Test").unwrap();
+    let result = FolderCompare::new(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()]).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    let tree = result.diff_tree();
+    let children: Vec<_> = tree.children.iter().map(|child| (child.name.to_str().unwrap(), child.status)).collect();
+    assert_eq!(tree.status, FileStatus::Changed);
+    assert_eq!(children, vec![
+        ("added", FileStatus::New),
+        ("same", FileStatus::Unchanged),
+        ("test.abc", FileStatus::New),
+        ("test.xls", FileStatus::Changed),
+    ]);
+}
+
+#[test]
+fn per_file_timeout_lists_slow_files() {
+    let dirs = prepare_environment_in("compare_per_file_timeout").unwrap();
+    fs::write(dirs.0.join("test.xls"), vec![0; 64 * 1024 * 1024]).unwrap();
+    fs::write(dirs.1.join("test.xls"), vec![1; 64 * 1024 * 1024]).unwrap();
+    let excluded = vec![".txt".to_string()];
+    let options = CompareOptions { per_file_timeout: Some(Duration::ZERO), ..Default::default() };
+    let timed_out = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &excluded, &options).unwrap();
+    let generous = CompareOptions { per_file_timeout: Some(Duration::from_secs(60)), ..Default::default() };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &excluded, &generous).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!((result.changed_files.len(), result.timed_out.len()), (1, 0));
+    assert_eq!(timed_out.changed_files.len(), 0);
+    assert_eq!(timed_out.timed_out, vec![dirs.0.join("test.xls")]);
+}
+
+#[test]
+fn trailing_nulls_are_ignored() {
+    let dirs = prepare_environment_in("compare_trailing_nulls").unwrap();
+    fs::write(dirs.0.join("test.xls"), b"Test2\0\0\0").unwrap();
+    fs::write(dirs.0.join("padded.bin"), [&b"Test"[..], &[0; 100_000]].concat()).unwrap();
+    fs::write(dirs.1.join("padded.bin"), b"Test\0").unwrap();
+    fs::write(dirs.0.join("inner.bin"), b"Te\0st").unwrap();
+    fs::write(dirs.1.join("inner.bin"), b"Test").unwrap();
+    let options = CompareOptions { ignore_trailing_nulls: true, ..Default::default() };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(result.changed_files, vec![dirs.0.join("inner.bin")]);
+    assert_eq!(result.unchanged_files.len(), 2);
+}
+
+#[test]
+fn change_threshold_ignores_small_changes() {
+    let dirs = prepare_environment_in("compare_change_threshold").unwrap();
+    let content = vec![b'x'; 100 * 4096];
+    let (mut header, mut half) = (content.clone(), content.clone());
+    header[..16].fill(b'y');
+    half[..50 * 4096].fill(b'y');
+    for (name, changed) in [("header.bin", &header), ("half.bin", &half)] {
+        fs::write(dirs.0.join(name), changed).unwrap();
+        fs::write(dirs.1.join(name), &content).unwrap();
+    }
+    let options = CompareOptions { change_threshold: Some(0.05), ..Default::default() };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(result.unchanged_files, vec![dirs.0.join("header.bin")]);
+    assert!(result.changed_files.contains(&dirs.0.join("half.bin")));
+    assert!(result.changed_files.contains(&dirs.0.join("test.xls")));
+}
+
+#[test]
+fn itemized_output_matches_rsync() {
+    let dirs = prepare_environment_in("compare_itemized").unwrap();
+    fs::write(dirs.0.join("same.abc"), "Test").unwrap();
+    fs::write(dirs.1.join("same.abc"), "Test").unwrap();
+    fs::write(dirs.1.join("gone.abc"), "Test").unwrap();
+    let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+    for (dir, name, offset) in [(&dirs.0, "test.xls", 0), (&dirs.1, "test.xls", 0), (&dirs.0, "same.abc", 0), (&dirs.1, "same.abc", 1)] {
+        File::options().write(true).open(dir.join(name)).unwrap().set_modified(time + Duration::from_secs(offset)).unwrap();
+    }
+    let options = CompareOptions { detect_deleted: true, ..Default::default() };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &options).unwrap();
+    let mut itemized = vec![];
+    result.write_itemized(&mut itemized).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(String::from_utf8(itemized).unwrap(), "\
+*deleting   gone.abc
+.f..t...... same.abc
+>f+++++++++ test.abc
+>fcs....... test.xls
+");
+}
+
+#[cfg(unix)]
+#[test]
+fn json_lines_escape_quotes_and_invalid_paths() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    use folder_compare::write_jsonl;
+
+    let dirs = prepare_environment_in("compare_jsonl").unwrap();
+    fs::write(dirs.0.join("quo\"te.abc"), "Test").unwrap();
+    fs::write(dirs.0.join(OsStr::from_bytes(b"bad\xff.abc")), "Test").unwrap();
+    let mut written = vec![];
+    write_jsonl(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &mut written).unwrap();
+    let mut lines: Vec<String> = String::from_utf8(written).unwrap().lines().map(String::from).collect();
+    lines.sort();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    let a = dirs.0.display();
+    assert_eq!(lines, vec![
+        format!(r#"{{"status":"changed","path":"{}/test.xls"}}"#, a),
+        format!("{{\"status\":\"new\",\"path\":\"{}/bad\u{fffd}.abc\"}}", a),
+        format!(r#"{{"status":"new","path":"{}/quo\"te.abc"}}"#, a),
+        format!(r#"{{"status":"new","path":"{}/test.abc"}}"#, a),
+    ]);
+}
+
+#[test]
+fn only_files_newer_in_first_directory_are_changed() {
+    let dirs = prepare_environment_in("compare_newer").unwrap();
+    let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+    for (name, content2, offset1, offset2) in [("newer.abc", "Old", 10, 0), ("older.abc", "New", 0, 10), ("same.abc", "Other", 0, 0)] {
+        fs::write(dirs.0.join(name), "Test").unwrap();
+        fs::write(dirs.1.join(name), content2).unwrap();
+        File::options().write(true).open(dirs.0.join(name)).unwrap().set_modified(time + Duration::from_secs(offset1)).unwrap();
+        File::options().write(true).open(dirs.1.join(name)).unwrap().set_modified(time + Duration::from_secs(offset2)).unwrap();
+    }
+    let options = CompareOptions { mode: CompareMode::Newer, ..Default::default() };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string(), ".xls".to_string()], &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(result.changed_files, vec![dirs.0.join("newer.abc")]);
+    assert_eq!(result.unchanged_files, vec![dirs.0.join("older.abc"), dirs.0.join("same.abc")]);
+    assert_eq!(result.new_files, vec![dirs.0.join("test.abc")]);
+}
+
+#[test]
+fn modification_times_within_granularity_are_equal() {
+    let dirs = prepare_environment_in("compare_mtime_granularity").unwrap();
+    let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+    for (dir, name, nanos) in [(&dirs.0, "test.xls", 0), (&dirs.1, "test.xls", 0), (&dirs.0, "test.abc", 0), (&dirs.1, "test.abc", 500)] {
+        fs::write(dir.join(name), "Test").unwrap();
+        File::options().write(true).open(dir.join(name)).unwrap().set_modified(time + Duration::from_nanos(nanos)).unwrap();
+    }
+    let result = FolderCompare::new(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()]).unwrap();
+    let mut itemized = vec![];
+    result.write_itemized(&mut itemized).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(String::from_utf8(itemized).unwrap(), "");
+}
+
+#[test]
+fn only_common_files_are_compared() {
+    let dirs = prepare_environment_in("compare_common").unwrap();
+    fs::write(dirs.0.join("same.abc"), "Test").unwrap();
+    fs::write(dirs.1.join("same.abc"), "Test").unwrap();
+    fs::write(dirs.1.join("gone.abc"), "Test").unwrap();
+    let result = FolderCompare::compare_common(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()]).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(result.changed_files, vec![dirs.0.join("test.xls")]);
+    assert_eq!(result.unchanged_files, vec![dirs.0.join("same.abc")]);
+    assert!(result.new_files.is_empty());
+    assert!(result.deleted_files.is_empty());
+}
+
+#[test]
+fn only_subpaths_are_compared() {
+    let dirs = prepare_environment_in("compare_subpaths").unwrap();
+    for dir in [&dirs.0, &dirs.1] {
+        for sub in ["src", "config", "target"] {
+            create_dir(dir.join(sub)).unwrap();
+            fs::write(dir.join(sub).join("test.abc"), dir.to_str().unwrap()).unwrap();
+        }
+    }
+    let options = CompareOptions { subpaths: vec![PathBuf::from("src"), PathBuf::from("config")], ..Default::default() };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![], &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    let mut changed = result.changed_files;
+    changed.sort();
+    assert_eq!(changed, vec![dirs.0.join("config").join("test.abc"), dirs.0.join("src").join("test.abc")]);
+    assert!(result.new_files.is_empty());
+}
+
+#[test]
+fn listed_paths_are_classified_without_walking() {
+    let dirs = prepare_environment_in("compare_from_iter").unwrap();
+    fs::write(dirs.1.join("test.old"), "Test").unwrap();
+    fs::write(dirs.0.join("unlisted.abc"), "Test").unwrap();
+    let options = CompareOptions { detect_deleted: true, ..Default::default() };
+    let paths = ["test.abc", "test.xls", "test.old", "test.txt", "missing.abc"];
+    let result = FolderCompare::from_iter(dirs.0.as_path(), dirs.1.as_path(), paths, &vec![".txt".to_string()], &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(result.new_files, vec![dirs.0.join("test.abc")]);
+    assert_eq!(result.changed_files, vec![dirs.0.join("test.xls")]);
+    assert_eq!(result.deleted_files, vec![dirs.1.join("test.old")]);
+}
+
+#[test]
+fn listed_paths_escaping_the_roots_are_rejected() {
+    let dirs = prepare_environment_in("compare_from_iter_confined").unwrap();
+    let outside = dirs.0.parent().unwrap().join("outside");
+    create_dir(&outside).unwrap();
+    fs::write(outside.join("x"), "Test").unwrap();
+    let options = CompareOptions { confine_to_roots: true, ..Default::default() };
+    let rejected = FolderCompare::from_iter(dirs.0.as_path(), dirs.1.as_path(), ["../outside/x"], &vec![], &options);
+    let options = CompareOptions { continue_on_error: true, ..options };
+    let result = FolderCompare::from_iter(dirs.0.as_path(), dirs.1.as_path(), ["../outside/x", "test.abc"], &vec![], &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert!(rejected.is_err());
+    assert_eq!(result.new_files, vec![dirs.0.join("test.abc")]);
+    assert_eq!(result.errors.len(), 1);
+    assert_eq!(result.errors[0].kind, std::io::ErrorKind::PermissionDenied);
+}
+
+#[test]
+fn progress_is_reported_in_files() {
+    let dirs = prepare_environment_in("compare_progress_files").unwrap();
+    fs::write(dirs.1.join("test.old"), "Test").unwrap();
+    let options = CompareOptions { detect_deleted: true, ..Default::default() };
+    let mut reports = vec![];
+    FolderCompare::compare_with_progress_files(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &options, |done, total| {
+        reports.push((done, total));
+    }).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(reports, vec![(1, 3), (2, 3), (3, 3)]);
+}
+
+#[test]
+fn progress_is_reported_as_fraction() {
+    let dirs = prepare_environment_in("compare_progress_fraction").unwrap();
+    let mut fractions = vec![];
+    FolderCompare::compare_with_progress_fraction(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &CompareOptions::default(), |fraction| {
+        fractions.push(fraction);
+    }).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(fractions, vec![0.5, 1.0]);
+}
+
+#[test]
+fn paths_differing_in_case_are_paired() {
+    let dirs = prepare_environment_in("compare_case_insensitive_paths").unwrap();
+    fs::write(dirs.1.join("TEST.ABC"), "Test").unwrap();
+    let options = CompareOptions { case_insensitive_paths: true, detect_deleted: true, ..Default::default() };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &options).unwrap();
+    fs::write(dirs.0.join("Test.xls"), "Test").unwrap();
+    let collision = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &options);
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(result.unchanged_files, vec![dirs.0.join("test.abc")]);
+    assert!(result.new_files.is_empty() && result.deleted_files.is_empty());
+    assert!(matches!(collision, Err(folder_compare::Error::CaseCollision(_, _))));
+}
+
+#[test]
+fn diff_rq_report_matches_diff() {
+    let dirs = prepare_environment_in("compare_diff_rq").unwrap();
+    create_dir(dirs.0.join("added")).unwrap();
+    fs::write(dirs.0.join("added").join("one.abc"), "Test").unwrap();
+    fs::write(dirs.0.join("added").join("two.abc"), "Test").unwrap();
+    fs::write(dirs.1.join("old.abc"), "Test").unwrap();
+    let options = CompareOptions { detect_deleted: true, ..Default::default() };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &options).unwrap();
+    let mut report = vec![];
+    result.write_diff_rq(&mut report).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    let (a, b) = (dirs.0.display(), dirs.1.display());
+    assert_eq!(String::from_utf8(report).unwrap(), format!("\
+Only in {a}: added
+Only in {b}: old.abc
+Only in {a}: test.abc
+Files {a}/test.xls and {b}/test.xls differ
+", a = a, b = b));
+}
+
+#[cfg(unix)]
+#[test]
+fn diff_rq_report_equals_output_of_diff() {
+    use std::process::Command;
+
+    let dirs = prepare_environment_in("compare_diff_rq_exact").unwrap();
+    for dir in [&dirs.0, &dirs.1] {
+        fs::create_dir_all(dir.join("sub").join("nested")).unwrap();
+        fs::write(dir.join("sub").join("same.abc"), "Test").unwrap();
+    }
+    fs::write(dirs.0.join("sub").join("nested").join("changed.abc"), "Test").unwrap();
+    fs::write(dirs.1.join("sub").join("nested").join("changed.abc"), "Test2").unwrap();
+    fs::create_dir_all(dirs.1.join("removed").join("deep")).unwrap();
+    fs::write(dirs.1.join("removed").join("deep").join("old.abc"), "Test").unwrap();
+    fs::write(dirs.1.join("sub.abc"), "Test").unwrap();
+    let options = CompareOptions { detect_deleted: true, ..Default::default() };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![], &options).unwrap();
+    let mut report = vec![];
+    result.write_diff_rq(&mut report).unwrap();
+    let diff = Command::new("diff").arg("-rq").arg(&dirs.0).arg(&dirs.1).env("LC_ALL", "C").output().unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(String::from_utf8(report).unwrap(), String::from_utf8(diff.stdout).unwrap());
+}
+
+#[test]
+fn directories_left_empty_are_listed() {
+    let dirs = prepare_environment_in("compare_newly_empty_dirs").unwrap();
+    let nested = dirs.1.join("gone").join("nested");
+    fs::create_dir_all(&nested).unwrap();
+    fs::write(nested.join("old.abc"), "Test").unwrap();
+    fs::write(dirs.1.join("gone").join("old.abc"), "Test").unwrap();
+    create_dir(dirs.1.join("kept")).unwrap();
+    fs::write(dirs.1.join("kept").join("old.abc"), "Test").unwrap();
+    fs::write(dirs.1.join("kept").join("notes.txt"), "Test").unwrap();
+    let options = CompareOptions { detect_deleted: true, ..Default::default() };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &options).unwrap();
+    let empty = result.newly_empty_dirs();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(empty, vec![nested, dirs.1.join("gone")]);
+}
+
+#[test]
+fn reclassify_overrides_the_status() {
+    let dirs = prepare_environment_in("compare_reclassify").unwrap();
+    let options = CompareOptions {
+        reclassify: Some(Reclassify::new(|path, status| match path.extension() {
+            Some(extension) if extension == "xls" && status == FileStatus::Changed => FileStatus::Unchanged,
+            _ => status,
+        })),
+        ..Default::default()
+    };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert!(result.changed_files.is_empty());
+    assert_eq!(result.unchanged_files, vec![dirs.0.join("test.xls")]);
+    assert_eq!(result.new_files, vec![dirs.0.join("test.abc")]);
+}
+
+#[test]
+fn limit_stops_after_as_many_differences() {
+    let dirs = prepare_environment_in("compare_limit").unwrap();
+    fs::write(dirs.0.join("more.abc"), "Test").unwrap();
+    let excluded = vec![".txt".to_string()];
+    let options = CompareOptions { limit: Some(2), ..Default::default() };
+    let limited = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &excluded, &options).unwrap();
+    let options = CompareOptions { limit: Some(3), ..Default::default() };
+    let complete = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &excluded, &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(limited.new_files.len() + limited.changed_files.len(), 2);
+    assert!(limited.truncated);
+    assert_eq!((complete.new_files.len(), complete.changed_files.len()), (2, 1));
+    assert!(!complete.truncated);
+}
+
+#[test]
+fn entry_kinds_select_files_and_directories() {
+    let dirs = prepare_environment_in("compare_entry_kinds").unwrap();
+    for dir in [dirs.0.join("both"), dirs.1.join("both"), dirs.0.join("only_a"), dirs.1.join("only_b")] {
+        create_dir(dir).unwrap();
+    }
+    let excluded = vec![".txt".to_string()];
+    let compare = |entry_kinds| {
+        let options = CompareOptions { entry_kinds, detect_deleted: true, ..Default::default() };
+        let mut result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &excluded, &options).unwrap();
+        result.new_files.sort();
+        result
+    };
+    let only_dirs = compare(EntryKinds::Dirs);
+    let both = compare(EntryKinds::Both);
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(only_dirs.new_files, vec![dirs.0.join("only_a")]);
+    assert_eq!(only_dirs.unchanged_files, vec![dirs.0.join("both")]);
+    assert_eq!(only_dirs.deleted_files, vec![dirs.1.join("only_b")]);
+    assert!(only_dirs.changed_files.is_empty());
+    assert_eq!(both.new_files, vec![dirs.0.join("only_a"), dirs.0.join("test.abc")]);
+    assert_eq!(both.changed_files, vec![dirs.0.join("test.xls")]);
+    assert_eq!(both.deleted_files, vec![dirs.1.join("only_b")]);
+}
+
+#[cfg(unix)]
+#[test]
+fn backslashes_separate_counterparts_with_normalize_separators() {
+    let dirs = prepare_environment_in("compare_normalize_separators").unwrap();
+    fs::write(dirs.0.join("sub\\test.abc"), "Test").unwrap();
+    create_dir(dirs.1.join("sub")).unwrap();
+    fs::write(dirs.1.join("sub").join("test.abc"), "Test").unwrap();
+    let excluded = vec![".txt".to_string()];
+    let plain = FolderCompare::new(dirs.0.as_path(), dirs.1.as_path(), &excluded).unwrap();
+    let options = CompareOptions { normalize_separators: true, ..Default::default() };
+    let normalized = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &excluded, &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert!(plain.new_files.contains(&dirs.0.join("sub\\test.abc")));
+    assert_eq!(normalized.unchanged_files, vec![dirs.0.join("sub\\test.abc")]);
+    assert_eq!(normalized.new_files, vec![dirs.0.join("test.abc")]);
+}
+
+#[test]
+fn ignore_case_compares_text_files_only_case_insensitively() {
+    let dirs = prepare_environment_in("compare_ignore_case").unwrap();
+    fs::write(dirs.0.join("text.abc"), "TRUE\n").unwrap();
+    fs::write(dirs.1.join("text.abc"), "true\n").unwrap();
+    fs::write(dirs.0.join("binary.abc"), b"TRUE\0").unwrap();
+    fs::write(dirs.1.join("binary.abc"), b"true\0").unwrap();
+    let excluded = vec!["test\\.".to_string()];
+    let plain = FolderCompare::new(dirs.0.as_path(), dirs.1.as_path(), &excluded).unwrap();
+    let options = CompareOptions { ignore_case: true, ..Default::default() };
+    let ignoring = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &excluded, &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(plain.changed_files.len(), 2);
+    assert_eq!(ignoring.unchanged_files, vec![dirs.0.join("text.abc")]);
+    assert_eq!(ignoring.changed_files, vec![dirs.0.join("binary.abc")]);
+}
+
+#[test]
+fn files_outside_the_size_filter_are_left_out() {
+    let dirs = prepare_environment_in("compare_size_filter").unwrap();
+    fs::write(dirs.0.join("empty.abc"), "").unwrap();
+    fs::write(dirs.0.join("large.abc"), "Test Test Test").unwrap();
+    fs::write(dirs.1.join("large.old"), "Test Test Test").unwrap();
+    fs::write(dirs.1.join("small.old"), "Test").unwrap();
+    let options = CompareOptions { size_filter: Some(1..8), detect_deleted: true, ..Default::default() };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(result.new_files, vec![dirs.0.join("test.abc")]);
+    assert_eq!(result.changed_files, vec![dirs.0.join("test.xls")]);
+    assert_eq!(result.deleted_files, vec![dirs.1.join("small.old")]);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn unreadable_files_are_listed_with_continue_on_error() {
+    let dirs = prepare_environment_in("compare_continue_on_error").unwrap();
+    //reading the start of /proc/self/mem fails even for root
+    std::os::unix::fs::symlink("/proc/self/mem", dirs.0.join("mem")).unwrap();
+    std::os::unix::fs::symlink("/proc/self/mem", dirs.1.join("mem")).unwrap();
+    let excluded = vec![".txt".to_string()];
+    let options = CompareOptions { symlinks: Symlinks::Follow, ..Default::default() };
+    let failed = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &excluded, &options).is_err();
+    let options = CompareOptions { continue_on_error: true, ..options };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &excluded, &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    let errors: Vec<_> = result.errors.iter().map(|error| error.path.clone()).collect();
+    assert!(failed);
+    assert_eq!(errors, vec![dirs.0.join("mem")]);
+    assert_eq!(result.new_files, vec![dirs.0.join("test.abc")]);
+    assert_eq!(result.changed_files, vec![dirs.0.join("test.xls")]);
+    assert!(result.unchanged_files.is_empty());
+}
+
+#[test]
+fn only_sampled_blocks_are_compared_with_sample_offsets() {
+    let dirs = prepare_environment_in("compare_sample_offsets").unwrap();
+    let content = vec![0; 64 * 1024];
+    for (name, changed_at) in [("middle.abc", 32 * 1024), ("start.abc", 0)] {
+        let mut changed = content.clone();
+        changed[changed_at] = 1;
+        fs::write(dirs.0.join(name), &content).unwrap();
+        fs::write(dirs.1.join(name), changed).unwrap();
+    }
+    let excluded = vec!["test\\.".to_string()];
+    let plain = FolderCompare::new(dirs.0.as_path(), dirs.1.as_path(), &excluded).unwrap();
+    let options = CompareOptions { sample_offsets: Some(vec![0.0, 1.0]), ..Default::default() };
+    let sampled = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &excluded, &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(plain.changed_files.len(), 2);
+    assert_eq!(sampled.unchanged_files, vec![dirs.0.join("middle.abc")]);
+    assert_eq!(sampled.changed_files, vec![dirs.0.join("start.abc")]);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn permanent_errors_are_not_retried() {
+    let dirs = prepare_environment_in("compare_io_retries").unwrap();
+    std::os::unix::fs::symlink("/proc/self/mem", dirs.0.join("mem")).unwrap();
+    std::os::unix::fs::symlink("/proc/self/mem", dirs.1.join("mem")).unwrap();
+    //8 retries would sleep for 2.55 s in total
+    let options = CompareOptions { symlinks: Symlinks::Follow, continue_on_error: true, io_retries: 8, ..Default::default() };
+    let started = std::time::Instant::now();
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &options).unwrap();
+    let elapsed = started.elapsed();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    let errors: Vec<_> = result.errors.iter().map(|error| error.path.clone()).collect();
+    assert_eq!(errors, vec![dirs.0.join("mem")]);
+    assert!(elapsed < Duration::from_secs(2));
+}
+
+#[test]
+fn soft_excluded_files_are_never_changed() {
+    let dirs = prepare_environment_in("compare_soft_excluded").unwrap();
+    fs::write(dirs.1.join("gone.xls"), "Test").unwrap();
+    let options = CompareOptions {
+        soft_excluded: vec!["\\.(xls|txt)$".to_string()],
+        detect_deleted: true,
+        ..Default::default()
+    };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert!(result.changed_files.is_empty());
+    assert_eq!(result.unchanged_files, vec![dirs.0.join("test.xls")]);
+    assert_eq!(result.new_files, vec![dirs.0.join("test.abc")]);
+    assert_eq!(result.deleted_files, vec![dirs.1.join("gone.xls")]);
+}
+
+#[test]
+fn paths_are_stored_relative_to_the_display_base() {
+    let dirs = prepare_environment_in("compare_display_base").unwrap();
+    fs::write(dirs.1.join("gone.old"), "Test").unwrap();
+    let options = CompareOptions {
+        display_base: Some(dirs.0.parent().unwrap().to_path_buf()),
+        detect_deleted: true,
+        ..Default::default()
+    };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!((result.path1.as_path(), result.path2.as_path()), (Path::new("a"), Path::new("b")));
+    assert_eq!(result.new_files, vec![Path::new("a").join("test.abc")]);
+    assert_eq!(result.changed_files, vec![Path::new("a").join("test.xls")]);
+    assert_eq!(result.deleted_files, vec![Path::new("b").join("gone.old")]);
+    assert_eq!(result.statuses().get(Path::new("gone.old")), Some(&FileStatus::Deleted));
+}
+
+#[test]
+fn binary_differences_are_detected() {
+    let dirs = prepare_environment_in("compare_detect_binary").unwrap();
+    fs::write(dirs.0.join("new.bin"), b"Test\0").unwrap();
+    fs::write(dirs.0.join("same.bin"), b"Test\0").unwrap();
+    fs::write(dirs.1.join("same.bin"), b"Test\0").unwrap();
+    let options = CompareOptions { detect_binary: true, ..Default::default() };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(result.new_files.len(), 2);
+    assert_eq!(result.changed_files, vec![dirs.0.join("test.xls")]);
+    assert_eq!(result.binary_files, vec![dirs.0.join("new.bin")]);
+}
+
+#[test]
+fn overlapping_subpaths_and_repeated_paths_are_listed_once() {
+    let dirs = prepare_environment_in("compare_overlapping_subpaths").unwrap();
+    for dir in [&dirs.0, &dirs.1] {
+        create_dir(dir.join("src")).unwrap();
+        create_dir(dir.join("src").join("bin")).unwrap();
+        fs::write(dir.join("src").join("bin").join("test.abc"), dir.to_str().unwrap()).unwrap();
+    }
+    let options = CompareOptions {
+        subpaths: vec![PathBuf::from("src"), PathBuf::from("src/bin"), PathBuf::from("src")],
+        ..Default::default()
+    };
+    let walked = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![], &options).unwrap();
+    let listed = FolderCompare::from_iter(dirs.0.as_path(), dirs.1.as_path(), ["test.xls", "test.xls"], &vec![], &CompareOptions::default()).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(walked.changed_files, vec![dirs.0.join("src").join("bin").join("test.abc")]);
+    assert!(walked.new_files.is_empty());
+    assert_eq!(listed.changed_files, vec![dirs.0.join("test.xls")]);
+}
+
+#[test]
+fn soft_excluded_files_stay_unchanged_with_strict_metadata() {
+    let dirs = prepare_environment_in("compare_soft_excluded_strict").unwrap();
+    let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+    for (dir, offset) in [(&dirs.0, 0), (&dirs.1, 5)] {
+        fs::write(dir.join("out.gen"), "Test").unwrap();
+        File::options().write(true).open(dir.join("out.gen")).unwrap().set_modified(time + Duration::from_secs(offset)).unwrap();
+    }
+    let options = CompareOptions {
+        soft_excluded: vec!["\\.gen$".to_string()],
+        strict_metadata: true,
+        ..Default::default()
+    };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec!["test\\.".to_string()], &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(result.unchanged_files, vec![dirs.0.join("out.gen")]);
+    assert!(result.changed_files.is_empty());
+    assert!(result.metadata_differences.is_empty());
+}
+
+#[test]
+fn streamed_comparisons_stop_at_the_limit() {
+    #[derive(Default)]
+    struct Count(usize);
+
+    impl ResultSink for Count {
+        fn on_new(&mut self, _path: &Path) {
+            self.0 += 1;
+        }
+
+        fn on_changed(&mut self, _path: &Path) {
+            self.0 += 1;
+        }
+    }
+
+    let dirs = prepare_environment_in("compare_streamed_limit").unwrap();
+    let excluded = vec![".txt".to_string()];
+    let mut unlimited = Count::default();
+    Comparator::new(&excluded, CompareOptions::default()).unwrap().compare_into(dirs.0.as_path(), dirs.1.as_path(), &mut unlimited).unwrap();
+    let mut limited = Count::default();
+    let options = CompareOptions { limit: Some(1), ..Default::default() };
+    Comparator::new(&excluded, options).unwrap().compare_into(dirs.0.as_path(), dirs.1.as_path(), &mut limited).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!((unlimited.0, limited.0), (2, 1));
+}
+
+#[test]
+fn changes_since_a_recorded_fingerprint_are_detected() {
+    let dirs = prepare_environment_in("compare_has_changed_since").unwrap();
+    let excluded = vec![".txt".to_string()];
+    let fingerprint = tree_hash(&dirs.0, &excluded).unwrap();
+    let unchanged = has_changed_since(&dirs.0, fingerprint, &excluded).unwrap();
+    fs::write(dirs.0.join("test.txt"), "Test2").unwrap();
+    let excluded_changed = has_changed_since(&dirs.0, fingerprint, &excluded).unwrap();
+    fs::rename(dirs.0.join("test.abc"), dirs.0.join("test.abd")).unwrap();
+    let renamed = has_changed_since(&dirs.0, fingerprint, &excluded).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert!(!unchanged);
+    assert!(!excluded_changed);
+    assert!(renamed);
+}
+
+#[test]
+fn change_thresholds_outside_of_0_to_1_are_rejected() {
+    let dirs = prepare_environment_in("compare_invalid_change_threshold").unwrap();
+    let rejected: Vec<bool> = [-0.1, 1.5, f64::NAN].iter().map(|&threshold| {
+        let options = CompareOptions { change_threshold: Some(threshold), ..Default::default() };
+        let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![], &options);
+        matches!(result, Err(folder_compare::Error::InvalidOption("change_threshold")))
+    }).collect();
+    let options = CompareOptions { change_threshold: Some(1.0), ..Default::default() };
+    let accepted = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![], &options).is_ok();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(rejected, vec![true, true, true]);
+    assert!(accepted);
+}
+
+#[test]
+fn invalid_shards_are_rejected() {
+    let dirs = prepare_environment_in("compare_invalid_shards").unwrap();
+    let rejected: Vec<bool> = [(0, 0), (3, 3), (4, 3)].iter().map(|&shard| {
+        let options = CompareOptions { shard: Some(shard), ..Default::default() };
+        let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![], &options);
+        matches!(result, Err(folder_compare::Error::InvalidOption("shard")))
+    }).collect();
+    let options = CompareOptions { shard: Some((0, 1)), ..Default::default() };
+    let single = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(rejected, vec![true, true, true]);
+    assert_eq!((single.new_files.len(), single.changed_files.len()), (1, 1));
+}
+
+#[test]
+fn files_are_verified_against_recorded_hashes() {
+    let dirs = prepare_environment_in("compare_verify_file").unwrap();
+    let expected = hash_file(&dirs.0.join("test.abc")).unwrap();
+    let matching = verify_file(&dirs.0.join("test.xls"), expected).unwrap();
+    let mismatching = verify_file(&dirs.1.join("test.xls"), expected).unwrap();
+    let missing = verify_file(&dirs.1.join("test.abc"), expected);
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert!(matching);
+    assert!(!mismatching);
+    assert!(missing.is_err());
+}
+
+#[test]
+fn byte_progress_reaches_the_total() {
+    let dirs = prepare_environment_in("compare_progress_bytes").unwrap();
+    fs::write(dirs.1.join("test.xls"), "Tesd").unwrap();
+    fs::write(dirs.1.join("test.abc"), "Test").unwrap();
+    let mut steps = vec![];
+    FolderCompare::compare_with_progress_bytes(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &CompareOptions::default(), |read, total| {
+        steps.push((read, total));
+    }).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(steps.last(), Some(&(16, 16)));
+    assert!(steps.iter().all(|&(read, total)| read <= total));
+}
+
+#[cfg(unix)]
+#[test]
+fn special_files_are_reported_with_strict_regular() {
+    let dirs = prepare_environment_in("compare_strict_regular").unwrap();
+    let _socket = std::os::unix::net::UnixListener::bind(dirs.0.join("test.sock")).unwrap();
+    let compare = |strict_regular| {
+        let options = CompareOptions { strict_regular, ..Default::default() };
+        FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &options).unwrap()
+    };
+    let strict = compare(true);
+    let lenient = compare(false);
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(strict.unexpected_entries, vec![dirs.0.join("test.sock")]);
+    assert!(!strict.new_files.contains(&dirs.0.join("test.sock")));
+    assert!(lenient.unexpected_entries.is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn ownership_changes_are_listed() {
+    let dirs = prepare_environment_in("compare_ownership").unwrap();
+    fs::write(dirs.1.join("test.abc"), "Test").unwrap();
+    let options = CompareOptions { compare_ownership: true, ..Default::default() };
+    let compare = || FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &options).unwrap();
+    let same_owner = compare();
+    // Only root may hand a file over to another user.
+    let handed_over = std::os::unix::fs::chown(dirs.1.join("test.abc"), Some(4242), Some(4242)).is_ok();
+    let other_owner = compare();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert!(same_owner.ownership_changed.is_empty());
+    if handed_over {
+        assert_eq!(other_owner.ownership_changed, vec![dirs.0.join("test.abc")]);
+    }
+}
+
+#[test]
+fn merging_overlapping_results_keeps_the_order_without_duplicates() {
+    let mut merged = FolderCompare {
+        path1: PathBuf::from("a"),
+        path2: PathBuf::from("b"),
+        changed_files: vec![PathBuf::from("a/one"), PathBuf::from("a/two")],
+        new_files: vec![PathBuf::from("a/new")],
+        deleted_files: vec![PathBuf::from("b/gone")],
+        ..Default::default()
+    };
+    merged.merge(FolderCompare {
+        path1: PathBuf::from("c"),
+        path2: PathBuf::from("d"),
+        changed_files: vec![PathBuf::from("a/two"), PathBuf::from("a/three")],
+        new_files: vec![PathBuf::from("a/one"), PathBuf::from("a/other")],
+        unchanged_files: vec![PathBuf::from("a/same")],
+        deleted_files: vec![PathBuf::from("b/lost"), PathBuf::from("b/gone")],
+        ..Default::default()
+    });
+
+    assert_eq!((merged.path1, merged.path2), (PathBuf::from("a"), PathBuf::from("b")));
+    assert_eq!(merged.changed_files, vec![PathBuf::from("a/one"), PathBuf::from("a/two"), PathBuf::from("a/three")]);
+    assert_eq!(merged.new_files, vec![PathBuf::from("a/new"), PathBuf::from("a/other")]);
+    assert_eq!(merged.unchanged_files, vec![PathBuf::from("a/same")]);
+    assert_eq!(merged.deleted_files, vec![PathBuf::from("b/gone"), PathBuf::from("b/lost")]);
+}
+
+#[test]
+fn files_differing_in_case_only_are_paired() {
+    let dirs = prepare_environment_in("compare_name_only").unwrap();
+    fs::write(dirs.1.join("TEST.abc"), "Test").unwrap();
+    fs::write(dirs.0.join("other.abc"), "Test").unwrap();
+    fs::write(dirs.1.join("Other.abc"), "Tesd").unwrap();
+    let options = CompareOptions { detect_name_only_differences: true, detect_deleted: true, ..Default::default() };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(result.name_only_differences, vec![(dirs.0.join("test.abc"), dirs.1.join("TEST.abc"))]);
+}
+
+#[test]
+fn changed_files_are_grouped_by_their_parent() {
+    let dirs = prepare_environment_in("compare_changed_by_dir").unwrap();
+    for dir in ["sub", "sub/nested"].iter() {
+        create_dir(dirs.0.join(dir)).unwrap();
+        create_dir(dirs.1.join(dir)).unwrap();
+    }
+    for name in ["sub/one", "sub/two", "sub/nested/three"].iter() {
+        fs::write(dirs.0.join(name), "Test").unwrap();
+        fs::write(dirs.1.join(name), "Other").unwrap();
+    }
+    let options = CompareOptions { order_by: OrderBy::PathAsc, ..Default::default() };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec!["test".to_string()], &options).unwrap();
+    let by_dir = result.changed_by_dir();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(by_dir.len(), 2);
+    assert_eq!(by_dir[&dirs.0.join("sub")], vec![dirs.0.join("sub/one"), dirs.0.join("sub/two")]);
+    assert_eq!(by_dir[&dirs.0.join("sub/nested")], vec![dirs.0.join("sub/nested/three")]);
+}
+
+#[test]
+fn moved_files_are_paired_by_content() {
+    let dirs = prepare_environment_in("compare_same_content").unwrap();
+    create_dir(dirs.1.join("moved")).unwrap();
+    fs::write(dirs.1.join("moved/renamed.abc"), "Test").unwrap();
+    let options = CompareOptions { detect_same_content: true, ..Default::default() };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec!["\\.(txt|xls)".to_string()], &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(result.same_content_different_path, vec![(dirs.0.join("test.abc"), dirs.1.join("moved/renamed.abc"))]);
+}
+
+#[test]
+fn identical_files_are_paired_across_paths() {
+    let dirs = prepare_environment_in("compare_identical_files").unwrap();
+    fs::write(dirs.1.join("copy"), "Test").unwrap();
+    fs::write(dirs.1.join("same_size"), "Tesd").unwrap();
+    let mut pairs = identical_files(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()]).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    pairs.sort();
+    assert_eq!(pairs, vec![(dirs.0.join("test.abc"), dirs.1.join("copy")), (dirs.0.join("test.xls"), dirs.1.join("copy"))]);
+}
+
+#[test]
+fn configured_walks_hide_nested_files() {
+    let dirs = prepare_environment_in("compare_configure_walk").unwrap();
+    create_dir(dirs.0.join("sub")).unwrap();
+    fs::write(dirs.0.join("sub/nested.abc"), "Test").unwrap();
+    let options = CompareOptions {
+        configure_walk: Some(ConfigureWalk::new(|walk| walk.max_depth(1))),
+        ..Default::default()
+    };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(result.new_files, vec![dirs.0.join("test.abc")]);
+    assert_eq!(result.changed_files, vec![dirs.0.join("test.xls")]);
+}
+
+#[test]
+fn same_file_system_keeps_files_of_the_same_file_system() {
+    let dirs = prepare_environment_in("compare_same_file_system").unwrap();
+    create_dir(dirs.0.join("sub")).unwrap();
+    fs::write(dirs.0.join("sub/nested.abc"), "Test").unwrap();
+    let options = CompareOptions { same_file_system: true, order_by: OrderBy::PathAsc, ..Default::default() };
+    let result = FolderCompare::with_options(dirs.0.as_path(), dirs.1.as_path(), &vec![".txt".to_string()], &options).unwrap();
+
+    remove_dir_all(dirs.1.parent().unwrap()).unwrap();
+    assert_eq!(result.new_files, vec![dirs.0.join("sub/nested.abc"), dirs.0.join("test.abc")]);
+    assert_eq!(result.changed_files, vec![dirs.0.join("test.xls")]);
+}
+
+#[test]
+fn sources_with_metadata_are_only_opened_where_needed() {
+    struct Counted {
+        files: Vec<(PathBuf, &'static str, Option<SystemTime>)>,
+        opened: Vec<PathBuf>,
+    }
+
+    impl FileSource for Counted {
+        fn files(&mut self) -> Result<Vec<PathBuf>, folder_compare::Error> {
+            Ok(self.files.iter().map(|file| file.0.clone()).collect())
+        }
+
+        fn metadata(&mut self, path: &Path) -> Result<Option<FileMetadata>, folder_compare::Error> {
+            Ok(self.files.iter().find(|file| file.0 == path).map(|file| FileMetadata { size: file.1.len() as u64, modified: file.2 }))
+        }
+
+        fn open(&mut self, path: &Path) -> Result<Option<Box<dyn std::io::Read + '_>>, folder_compare::Error> {
+            self.opened.push(path.to_path_buf());
+            Ok(self.files.iter().find(|file| file.0 == path).map(|file| Box::new(file.1.as_bytes()) as Box<dyn std::io::Read>))
+        }
+    }
+
+    let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+    let later = time + Duration::from_secs(60);
+    let mut first = Counted {
+        files: vec![
+            (PathBuf::from("same"), "Test", Some(time)),
+            (PathBuf::from("resized"), "Test", Some(time)),
+            (PathBuf::from("touched"), "Test", Some(later)),
+        ],
+        opened: vec![],
+    };
+    let mut second = Counted {
+        files: vec![
+            (PathBuf::from("same"), "Test", Some(time)),
+            (PathBuf::from("resized"), "Test2", Some(time)),
+            (PathBuf::from("touched"), "Test", Some(time)),
+        ],
+        opened: vec![],
+    };
+    let result = FolderCompare::from_sources(&mut first, &mut second, &vec![]).unwrap();
+
+    assert_eq!(result.changed_files, vec![PathBuf::from("resized")]);
+    assert_eq!(result.unchanged_files, vec![PathBuf::from("same"), PathBuf::from("touched")]);
+    assert_eq!(first.opened, vec![PathBuf::from("touched")]);
+    assert_eq!(second.opened, vec![PathBuf::from("touched")]);
+}
+
 fn prepare_environment() -> Result<(PathBuf, PathBuf), Error> {
+    prepare_environment_in("compare")
+}
+
+fn prepare_environment_in(name: &str) -> Result<(PathBuf, PathBuf), Error> {
     let mut base_dir = env::temp_dir();
-    base_dir.push("compare");
+    base_dir.push(name);
     create_dir(&base_dir)?;
 
     let mut dir_a = base_dir.clone();
@@ -43,4 +2076,4 @@ fn prepare_environment() -> Result<(PathBuf, PathBuf), Error> {
     fs::write(&dir_b, "Test2")?;
     dir_b.pop();
     Ok((dir_a, dir_b))
-}
\ No newline at end of file
+}